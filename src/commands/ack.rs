@@ -1,22 +1,24 @@
-use rsip::headers::{CSeq, Contact, UntypedHeader, UserAgent, Via};
+use rsip::headers::{CSeq, Contact, ToTypedHeader, UntypedHeader, UserAgent, Via};
 use rsip::{Header, SipMessage};
 
 use crate::state::options::SipOptions;
 
-use super::helper::get_base_uri;
+use super::helper::{get_base_uri, get_dial_uri, get_route_set, get_uri, get_via};
+use crate::parsers::get_user_from_uri;
 
 impl SipOptions {
-    pub fn create_ack(
-        &self,
-        via: &Via,
-        rr: Vec<&Header>,
-        cnt: &Contact,
-        _cseq: &CSeq,
-    ) -> SipMessage {
+    /// ACK for a 2xx, it is a transaction of its own and gets a new branch
+    pub fn create_ack(&self, rr: Vec<&Header>, cnt: &Contact, cseq: &CSeq) -> SipMessage {
         let mut headers: rsip::Headers = Default::default();
         let base_uri = get_base_uri(&self.extension, &self.sip_server, &self.sip_port);
+        let dial_uri = get_dial_uri(
+            self.cld.as_ref().unwrap(),
+            &self.sip_server,
+            &self.sip_port,
+            self.transport,
+        );
 
-        headers.push(Header::Via(via.clone()));
+        headers.push(get_via(&self.ip, &self.local_port, self.transport));
         headers.push(
             rsip::typed::From {
                 display_name: Some(self.username.to_string()),
@@ -26,27 +28,14 @@ impl SipOptions {
             .into(),
         );
 
-        let lroute = rr.last().unwrap().to_string();
-        let (_, route_value) = lroute.split_at(14).to_owned();
-
-        headers.push(rsip::Header::Route(rsip::headers::Route::new(
-            route_value.to_string(),
-        )));
+        for route in get_route_set(rr).into_iter().rev() {
+            headers.push(rsip::Header::Route(rsip::headers::Route::new(route)));
+        }
 
         headers.push(
             rsip::typed::To {
-                display_name: Some(self.cld.as_ref().unwrap().to_string()),
-                uri: rsip::Uri {
-                    auth: None,
-                    host_with_port: rsip::Domain::from(format!(
-                        "sip:{}@{}:{}",
-                        &self.cld.as_ref().unwrap().to_string(),
-                        &self.sip_server,
-                        &self.sip_port
-                    ))
-                    .into(),
-                    ..Default::default()
-                },
+                display_name: get_user_from_uri(&dial_uri),
+                uri: get_uri(&dial_uri),
                 params: vec![rsip::Param::Tag(rsip::param::Tag::new(
                     self.tag_remote.as_ref().unwrap(),
                 ))],
@@ -57,7 +46,7 @@ impl SipOptions {
         headers.push(rsip::headers::CallId::from(self.call_id.as_str()).into());
         headers.push(
             rsip::typed::CSeq {
-                seq: 2,
+                seq: cseq.typed().unwrap().seq,
                 method: rsip::Method::Ack,
             }
             .into(),
@@ -93,14 +82,21 @@ impl SipOptions {
 
         response
     }
-    pub fn create_basic_ack(&self, via: &Via, _cseq: &CSeq) -> SipMessage {
+    /// ACK for a non-2xx final response, it shares the Via of the INVITE
+    pub fn create_basic_ack(&self, via: &Via, cseq: &CSeq) -> SipMessage {
         let mut headers: rsip::Headers = Default::default();
         let base_uri = get_base_uri(&self.extension, &self.sip_server, &self.sip_port);
+        let dial_uri = get_dial_uri(
+            self.cld.as_ref().unwrap(),
+            &self.sip_server,
+            &self.sip_port,
+            self.transport,
+        );
 
         headers.push(
             rsip::typed::Via {
                 version: rsip::Version::V2,
-                transport: rsip::Transport::Udp,
+                transport: via.transport.clone(),
                 params: vec![
                     rsip::Param::Branch(via.branch().clone().unwrap()),
                     rsip::Param::Other("rport".into(), None),
@@ -122,17 +118,7 @@ impl SipOptions {
         headers.push(
             rsip::typed::To {
                 display_name: None,
-                uri: rsip::Uri {
-                    auth: None,
-                    host_with_port: rsip::Domain::from(format!(
-                        "sip:{}@{}:{}",
-                        &self.cld.as_ref().unwrap().to_string(),
-                        &self.sip_server,
-                        &self.sip_port
-                    ))
-                    .into(),
-                    ..Default::default()
-                },
+                uri: get_uri(&dial_uri),
                 params: vec![rsip::Param::Tag(rsip::param::Tag::new(
                     self.tag_remote.as_ref().unwrap(),
                 ))],
@@ -143,7 +129,7 @@ impl SipOptions {
         headers.push(rsip::headers::CallId::from(self.call_id.as_str()).into());
         headers.push(
             rsip::typed::CSeq {
-                seq: 1,
+                seq: cseq.typed().unwrap().seq,
                 method: rsip::Method::Ack,
             }
             .into(),
@@ -154,17 +140,7 @@ impl SipOptions {
 
         let response: SipMessage = rsip::Request {
             method: rsip::Method::Ack,
-            uri: rsip::Uri {
-                scheme: Some(rsip::Scheme::Sip),
-                host_with_port: rsip::Domain::from(format!(
-                    "{}@{}:{}",
-                    &self.cld.as_ref().unwrap().to_string(),
-                    &self.sip_server,
-                    &self.sip_port
-                ))
-                .into(),
-                ..Default::default()
-            },
+            uri: get_uri(&dial_uri),
             version: rsip::Version::V2,
             headers,
             body: Default::default(),
@@ -1,5 +1,8 @@
 use crate::{config::JSONConfiguration, state::options::SipOptions, util::random_string};
-use rsip::headers::auth::Qop;
+use rsip::headers::auth::{self, Qop};
+use rsip::{Header, StatusCode};
+
+use super::helper::{get_base_uri, get_host};
 
 #[derive(Debug, Clone)]
 pub struct AuthModel {
@@ -23,7 +26,7 @@ impl Auth for SipOptions {
             "{}:sip:{}@{}:{}",
             &String::from(method),
             &self.extension,
-            &self.sip_server,
+            get_host(&self.sip_server),
             &self.sip_port
         );
 
@@ -63,10 +66,43 @@ impl Auth for SipOptions {
     }
 }
 
+impl SipOptions {
+    /// Authorization for a 401, Proxy-Authorization for a 407, with the credentials
+    /// `set_auth` computed, the digest covers our own uri
+    pub fn get_auth_header(&self, code: &StatusCode) -> Header {
+        let auth = rsip::typed::Authorization {
+            scheme: auth::Scheme::Digest,
+            username: self.username.to_string(),
+            realm: self.realm.to_string(),
+            nonce: self.nonce.as_ref().unwrap().to_string(),
+            uri: get_base_uri(&self.extension, &self.sip_server, &self.sip_port),
+            response: self.md5.as_ref().unwrap().to_string(),
+            algorithm: Some(auth::Algorithm::Md5),
+            opaque: self.opaque.clone(),
+            qop: if self.qop {
+                Some(auth::AuthQop::Auth {
+                    cnonce: self.cnonce.as_ref().unwrap().to_string(),
+                    nc: self.nc.unwrap(),
+                })
+            } else {
+                None
+            },
+        };
+
+        if code == &StatusCode::Unauthorized {
+            auth.into()
+        } else {
+            rsip::typed::ProxyAuthorization(auth).into()
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        commands::auth::Auth, commands::auth::AuthModel, config::JSONConfiguration,
+        commands::auth::Auth,
+        commands::auth::AuthModel,
+        config::{JSONConfiguration, Transport},
         state::options::SipOptions,
     };
 
@@ -79,6 +115,7 @@ mod tests {
             sip_port: "5060".to_string(),
             branch: "it_doesnt_matter".to_string(),
             ip: "it_doesnt_matter".to_string(),
+            local_port: "5060".to_string(),
             nonce: None,
             md5: None,
             msg: None,
@@ -91,6 +128,7 @@ mod tests {
             qop: false,
             opaque: None,
             realm: "something".to_string(),
+            transport: Transport::Udp,
         };
 
         options.set_auth(
@@ -102,6 +140,15 @@ mod tests {
                 extension: "not_read_from_this_object".to_string(),
                 pcap: None,
                 reg_timeout: 120,
+                answer: Default::default(),
+                mwi: None,
+                blf: vec![],
+                transport: Transport::Udp,
+                tls: Default::default(),
+                ws_path: None,
+                local_ip: None,
+                local_sip_port: 5060,
+                interface: None,
             },
             &"REGISTER",
             &AuthModel {
@@ -0,0 +1,10 @@
+use rsip::SipMessage;
+
+use crate::state::{dialogs::Dialog, options::SipOptions};
+
+impl SipOptions {
+    /// Composes a BYE within an established dialog
+    pub fn create_bye(&self, dialog: &Dialog, cseq: u32) -> SipMessage {
+        self.create_in_dialog_request(dialog, rsip::Method::Bye, cseq, vec![], None)
+    }
+}
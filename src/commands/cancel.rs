@@ -0,0 +1,47 @@
+use rsip::headers::{UntypedHeader, UserAgent};
+use rsip::{message::HeadersExt, Header, Request, SipMessage};
+
+use crate::state::options::SipOptions;
+
+impl SipOptions {
+    /// Composes a CANCEL for a pending INVITE, it carries the same
+    /// Request-URI, top Via, Call-ID, From, To and CSeq number as the INVITE
+    pub fn create_cancel(&self, invite: &Request) -> SipMessage {
+        let mut headers: rsip::Headers = Default::default();
+
+        headers.push(invite.via_header().unwrap().clone().into());
+        headers.push(rsip::headers::MaxForwards::from(70).into());
+        headers.push(invite.from_header().unwrap().clone().into());
+        headers.push(invite.to_header().unwrap().clone().into());
+        headers.push(invite.call_id_header().unwrap().clone().into());
+        headers.push(
+            rsip::typed::CSeq {
+                seq: invite.cseq_header().unwrap().typed().unwrap().seq,
+                method: rsip::Method::Cancel,
+            }
+            .into(),
+        );
+
+        for route in invite
+            .headers
+            .iter()
+            .filter(|h| matches!(h, Header::Route(_)))
+        {
+            headers.push(route.clone());
+        }
+
+        headers.push(Header::UserAgent(UserAgent::new("Tiggy")));
+        headers.push(rsip::headers::ContentLength::default().into());
+
+        let request: SipMessage = rsip::Request {
+            method: rsip::Method::Cancel,
+            uri: invite.uri.clone(),
+            version: rsip::Version::V2,
+            headers,
+            body: Default::default(),
+        }
+        .into();
+
+        request
+    }
+}
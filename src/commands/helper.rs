@@ -1,42 +1,121 @@
 use nom::{
+    branch::alt,
     bytes::complete::{tag, take_until},
     error::Error,
-    sequence::tuple,
+    IResult,
 };
 use std::fmt::Write;
 
+use crate::config::Transport;
+use crate::state::dialogs::Dialog;
+use crate::util::random_string;
+
+/// Session interval we ask for (RFC 4028), in seconds
+pub const SESSION_EXPIRES: u32 = 1800;
+/// Shortest session interval we accept, the minimum RFC 4028 allows
+pub const MIN_SE: u32 = 90;
+
+/// Host as it goes into a uri, IPv6 literals within brackets
+pub fn get_host(host: &str) -> String {
+    match host.contains(':') && !host.starts_with('[') {
+        true => format!("[{}]", host),
+        false => host.to_string(),
+    }
+}
+
+/// Address type of the SDP connection and origin lines
+fn get_address_type(ip: &str) -> &'static str {
+    match ip.contains(':') {
+        true => "IP6",
+        false => "IP4",
+    }
+}
+
 pub fn get_base_uri(number: &str, server: &str, port: &str) -> rsip::Uri {
     rsip::Uri {
         auth: None,
-        host_with_port: rsip::Domain::from(format!("sip:{}@{}:{}", number, server, port)).into(),
+        host_with_port: rsip::Domain::from(format!("sip:{}@{}:{}", number, get_host(server), port))
+            .into(),
         ..Default::default()
     }
 }
 use chrono::prelude::*;
+use rsip::headers::UntypedHeader;
+
+/// Wraps an already formatted uri (sip:user@host:port) into an rsip::Uri
+pub fn get_uri(uri: &str) -> rsip::Uri {
+    rsip::Uri {
+        auth: None,
+        host_with_port: rsip::Domain::from(uri.to_string()).into(),
+        ..Default::default()
+    }
+}
 
-pub fn get_via(ip: &str, port: &str) -> rsip::Header {
+/// Branch of a new transaction, responses are matched against it
+pub fn get_branch() -> String {
     let now = Utc::now();
 
+    format!(
+        "z9hG4bK{}{}{}{}{}{}{}",
+        now.month(),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second(),
+        now.timestamp_millis(),
+        random_string(6)
+    )
+}
+
+pub fn get_via(ip: &str, port: &str, transport: Transport) -> rsip::Header {
     rsip::typed::Via {
         version: rsip::Version::V2,
-        transport: rsip::Transport::Udp,
+        transport: transport.into(),
         uri: rsip::Uri {
-            host_with_port: (rsip::Domain::from(format!("{}:{}", ip, port))).into(),
+            host_with_port: (rsip::Domain::from(format!("{}:{}", get_host(ip), port))).into(),
             ..Default::default()
         },
-        params: vec![rsip::Param::Branch(rsip::param::Branch::new(format!(
-            "z9hG4bK{}{}{}{}{}{}",
-            now.month(),
-            now.day(),
-            now.hour(),
-            now.minute(),
-            now.second(),
-            now.timestamp_millis()
-        )))],
+        params: vec![rsip::Param::Branch(rsip::param::Branch::new(get_branch()))],
     }
     .into()
 }
 
+/// Value of a Via parameter, e.g. `received` or `rport` (RFC 3581)
+fn get_via_param(via: &rsip::typed::Via, name: &str) -> Option<String> {
+    via.params.iter().find_map(|param| {
+        let param = param.to_string();
+        let (key, value) = param.trim_start_matches(';').split_once('=')?;
+        match key.trim().eq_ignore_ascii_case(name) {
+            true => Some(value.trim().to_string()),
+            false => None,
+        }
+    })
+}
+
+/// Address responses go to, the `received` of the Via when the sender is behind a NAT,
+/// the host in its sent-by otherwise
+pub fn get_via_host(via: &rsip::typed::Via) -> String {
+    get_via_param(via, "received").unwrap_or_else(|| via.uri.host().to_string())
+}
+
+/// Port responses go to, the `rport` of the Via, the one in its sent-by,
+/// or the default port of its transport, 5061 for TLS
+pub fn get_via_port(via: &rsip::typed::Via) -> u16 {
+    get_via_param(via, "rport")
+        .and_then(|rport| rport.parse().ok())
+        .or_else(|| {
+            via.uri
+                .host_with_port
+                .port
+                .as_ref()
+                .map(|port| *port.value())
+        })
+        .unwrap_or(match via.transport {
+            rsip::Transport::Tls => 5061,
+            _ => 5060,
+        })
+}
+
 pub fn get_from(username: &str, tag: &str, base_uri: rsip::Uri) -> rsip::Header {
     rsip::typed::From {
         display_name: Some(username.to_string()),
@@ -51,7 +130,13 @@ pub fn get_to(username: &str, did: &str, server: &str, port: &str) -> rsip::Head
         display_name: Some(username.to_string()),
         uri: rsip::Uri {
             auth: None,
-            host_with_port: rsip::Domain::from(format!("sip:{}@{}:{}", did, server, port)).into(),
+            host_with_port: rsip::Domain::from(format!(
+                "sip:{}@{}:{}",
+                did,
+                get_host(server),
+                port
+            ))
+            .into(),
             ..Default::default()
         },
         params: Default::default(),
@@ -59,11 +144,36 @@ pub fn get_to(username: &str, did: &str, server: &str, port: &str) -> rsip::Head
     .into()
 }
 
-pub fn get_contact(username: &str, did: &str, server: &str, port: &str) -> rsip::Header {
+pub fn get_contact(
+    username: &str,
+    did: &str,
+    server: &str,
+    port: &str,
+    transport: Transport,
+) -> rsip::Header {
+    let address = match transport {
+        // the gateway reaches us over the connection, not at an address
+        Transport::Ws | Transport::Wss => get_invalid_host(did, server),
+        _ => format!("{}:{}", get_host(server), port),
+    };
+    let params = match transport {
+        Transport::Udp => "",
+        Transport::Tcp => ";transport=tcp",
+        Transport::Tls => ";transport=tls",
+        Transport::Ws | Transport::Wss => ";transport=ws",
+    };
+
     rsip::typed::Contact {
         display_name: Some(username.to_string()),
         uri: rsip::Uri {
-            host_with_port: (rsip::Domain::from(format!("sip:{}@{}:{}", did, server, port))).into(),
+            host_with_port: (rsip::Domain::from(format!(
+                "{}:{}@{}{}",
+                transport.scheme(),
+                did,
+                address,
+                params
+            )))
+            .into(),
             ..Default::default()
         },
         params: Default::default(),
@@ -71,51 +181,145 @@ pub fn get_contact(username: &str, did: &str, server: &str, port: &str) -> rsip:
     .into()
 }
 
+/// Contact host over WebSocket (RFC 7118), stable per account but never resolvable
+pub fn get_invalid_host(did: &str, server: &str) -> String {
+    let digest = format!("{:x}", md5::compute(format!("{}@{}", did, server)));
+    format!("{}.invalid", &digest[..12])
+}
+
+/// First offer of a session, its first version receiving and sending
 pub fn get_fake_sdp(ip: &str) -> String {
+    get_sdp_with_direction(ip, 0, "sendrecv")
+}
+
+/// Our offer with a session version and a media direction, an SDP that
+/// differs from the last one of the session needs a higher version
+pub fn get_sdp_with_direction(ip: &str, version: u64, direction: &str) -> String {
     let mut body = "v=0\r\n".to_string();
-    let _ = write!(body, "o=tggVCE 226678890 391916715 IN IP4 {}\r\n", ip);
+    let _ = write!(
+        body,
+        "o=tggVCE 226678890 {} IN {} {}\r\n",
+        391916715 + version,
+        get_address_type(ip),
+        ip
+    );
     body.push_str("s=tggVCE Audio Call\r\n");
-    let _ = write!(body, "c=IN IP4 {}\r\n", ip);
+    let _ = write!(body, "c=IN {} {}\r\n", get_address_type(ip), ip);
     body.push_str("t=0 0\r\n");
     body.push_str("m=audio 49152 RTP/AVP 0 8 96\r\n");
     body.push_str("a=rtpmap:0 PCMU/8000\r\n");
     body.push_str("a=rtpmap:8 PCMA/8000\r\n");
     body.push_str("a=rtpmap:96 telephone-event/8000\r\n");
     body.push_str("a=fmtp:96 0-15\r\n");
+    let _ = write!(body, "a={}\r\n", direction);
 
     body
 }
 
-pub fn get_remote_tag(hstr: &str) -> &str {
-    let (rem, (_, _, _, _)): (&str, (&str, &str, &str, &str)) = tuple((
-        take_until::<&str, &str, Error<&str>>(";"),
-        tag(";"),
-        take_until("="),
-        tag("="),
-    ))(hstr)
-    .unwrap();
+/// Media direction an SDP body asks for, a body without a direction attribute is `sendrecv`
+pub fn get_sdp_direction(sdp: &[u8]) -> &'static str {
+    let sdp = String::from_utf8_lossy(sdp);
+    let direction = sdp.lines().rev().find_map(|line| match line.trim() {
+        "a=sendonly" => Some("sendonly"),
+        "a=recvonly" => Some("recvonly"),
+        "a=inactive" => Some("inactive"),
+        "a=sendrecv" => Some("sendrecv"),
+        _ => None,
+    });
+
+    direction.unwrap_or("sendrecv")
+}
+
+/// Media direction answering the one an SDP offer asks for (RFC 3264),
+/// a call we hold keeps not receiving whatever the offer asks for
+pub fn get_answer_direction(offer: &[u8], on_hold: bool) -> &'static str {
+    match (get_sdp_direction(offer), on_hold) {
+        ("sendonly", false) => "recvonly",
+        ("sendonly", true) | ("inactive", _) => "inactive",
+        ("recvonly", _) | (_, true) => "sendonly",
+        (direction, false) => direction,
+    }
+}
+
+/// Media direction of an offer of ours, a call we hold only sends
+/// and a call the other side holds only receives (RFC 3264)
+pub fn get_offer_direction(on_hold: bool, remote_hold: bool) -> &'static str {
+    match (on_hold, remote_hold) {
+        (false, false) => "sendrecv",
+        (true, false) => "sendonly",
+        (false, true) => "recvonly",
+        (true, true) => "inactive",
+    }
+}
+
+/// Connection address and audio port of an SDP body
+pub fn get_sdp_media(body: &[u8]) -> Option<(String, u16)> {
+    let sdp = String::from_utf8_lossy(body);
+    let mut address: Option<String> = None;
+    let mut port: Option<u16> = None;
+
+    for line in sdp.lines() {
+        if let Some(connection) = line.strip_prefix("c=") {
+            address = connection.split_whitespace().nth(2).map(|a| a.to_string());
+        } else if let Some(media) = line.strip_prefix("m=audio ") {
+            port = media.split_whitespace().next().and_then(|p| p.parse().ok());
+        }
+    }
+
+    Some((address?, port?))
+}
+
+/// Payload type the SDP body maps to telephone-event (RFC 4733)
+pub fn get_telephone_event_payload(body: &[u8]) -> Option<u8> {
+    String::from_utf8_lossy(body).lines().find_map(|line| {
+        let (payload, encoding) = line.strip_prefix("a=rtpmap:")?.split_once(' ')?;
+        match encoding.to_lowercase().starts_with("telephone-event/") {
+            true => payload.trim().parse().ok(),
+            false => None,
+        }
+    })
+}
 
-    rem
+/// Skips everything up to and including the `sip:` or `sips:` scheme of the uri
+fn skip_scheme(hstr: &str) -> IResult<&str, &str> {
+    let (rem, _) = take_until::<&str, &str, Error<&str>>("sip")(hstr)?;
+    match alt((tag::<&str, &str, Error<&str>>("sips:"), tag("sip:")))(rem) {
+        Ok((rem, scheme)) => Ok((rem, scheme)),
+        // "sip" as part of something else, a display name
+        Err(_) => skip_scheme(&rem[3..]),
+    }
 }
 
+/// User and host of the contact uri, with its port, 5060 when it has none,
+/// an IPv6 host keeps its brackets, the colons within it are not the port
 pub fn get_address_from_contact(hstr: String) -> (String, u16) {
-    let (rem, (_, _)): (&str, (&str, &str)) =
-        tuple((take_until::<&str, &str, Error<&str>>("sip:"), tag("sip:")))(&hstr).unwrap();
+    let (rem, _) = skip_scheme(&hstr).unwrap();
 
-    let mut chars = rem.chars();
-    chars.next_back();
-    let adur = chars.as_str();
-    let adur_split = adur.split(':').collect::<Vec<&str>>();
+    let adur = rem
+        .split(|c| c == '>' || c == ';')
+        .next()
+        .unwrap_or_default();
+    let (user, host) = match adur.rsplit_once('@') {
+        Some((user, host)) => (format!("{}@", user), host),
+        None => (String::new(), adur),
+    };
+    let (host, port) = match host.rfind(']') {
+        Some(end) => (&host[..=end], host[end + 1..].strip_prefix(':')),
+        None => match host.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (host, None),
+        },
+    };
 
     (
-        adur_split[0].to_string(),
-        adur_split[1].parse::<u16>().unwrap(),
+        format!("{}{}", user, host),
+        port.and_then(|port| port.trim().parse::<u16>().ok())
+            .unwrap_or(5060),
     )
 }
 
 pub fn get_address_from_record_route(hstr: String) -> String {
-    let (rem, (_, _)): (&str, (&str, &str)) =
-        tuple((take_until::<&str, &str, Error<&str>>("sip:"), tag("sip:")))(&hstr).unwrap();
+    let (rem, _) = skip_scheme(&hstr).unwrap();
 
     let mut chars = rem.chars();
     chars.next_back();
@@ -123,3 +327,332 @@ pub fn get_address_from_record_route(hstr: String) -> String {
     let adur_split = adur.split(';').collect::<Vec<&str>>();
     adur_split[0].to_string()
 }
+
+pub fn get_tag(params: &[rsip::Param]) -> Option<String> {
+    params.iter().find_map(|param| match param {
+        rsip::Param::Tag(tag) => Some(tag.to_string()),
+        _ => None,
+    })
+}
+
+/// Media type of the body, without parameters
+pub fn get_content_type(headers: &rsip::Headers) -> Option<String> {
+    headers.iter().find_map(|header| match header {
+        rsip::Header::ContentType(content_type) => content_type
+            .value()
+            .split(';')
+            .next()
+            .map(|media| media.trim().to_lowercase()),
+        _ => None,
+    })
+}
+
+/// Value of a header by name, the name is case insensitive, works for headers
+/// rsip parses into a variant (Event, Subscription-State) as well as for the rest
+pub fn get_other_header(headers: &rsip::Headers, name: &str) -> Option<String> {
+    headers.iter().find_map(|header| {
+        let header = header.to_string();
+        let (key, value) = header.split_once(':')?;
+        match key.trim().eq_ignore_ascii_case(name) {
+            true => Some(value.trim().to_string()),
+            false => None,
+        }
+    })
+}
+
+/// Session timer headers of a request within `dialog`, the refresher stays the same
+pub fn get_session_headers(dialog: &Dialog) -> Vec<rsip::Header> {
+    let interval = match dialog.session_expires {
+        Some(interval) => interval,
+        None => return vec![],
+    };
+    let refresher = if dialog.session_refresher {
+        "uac"
+    } else {
+        "uas"
+    };
+
+    vec![
+        rsip::Header::Other("Supported".into(), "timer".into()),
+        rsip::Header::Other(
+            "Session-Expires".into(),
+            format!("{};refresher={}", interval, refresher),
+        ),
+    ]
+}
+
+/// Refer-To uri of an attended transfer, `target` gets called with a Replaces
+/// header for the dialog identified by the call-id and the tags of the target side
+pub fn get_refer_to_with_replaces(
+    target: &str,
+    call_id: &str,
+    to_tag: &str,
+    from_tag: &str,
+) -> String {
+    let replaces = format!("{};to-tag={};from-tag={}", call_id, to_tag, from_tag)
+        .replace('%', "%25")
+        .replace('@', "%40")
+        .replace(';', "%3B")
+        .replace('=', "%3D");
+
+    format!("{}?Replaces={}", target, replaces)
+}
+
+/// Whether `target` is a `sip:` or `sips:` uri, anything else is a number
+pub fn is_sip_uri(target: &str) -> bool {
+    target.starts_with("sip:") || target.starts_with("sips:")
+}
+
+/// Uri a call to `destination` is addressed to, a `sip:` or `sips:` uri is taken as is
+/// without the headers it carries, an extension is a user of the server
+pub fn get_dial_uri(destination: &str, server: &str, port: &str, transport: Transport) -> String {
+    let uri = destination.trim().trim_start_matches('<');
+    let uri = uri.split('>').next().unwrap_or(uri);
+
+    match is_sip_uri(uri) {
+        true => uri.split('?').next().unwrap_or(uri).to_string(),
+        false => format!(
+            "{}:{}@{}:{}",
+            transport.scheme(),
+            uri,
+            get_host(server),
+            port
+        ),
+    }
+}
+
+/// message/sipfrag body carrying only a status line
+pub fn get_sipfrag(code: u16) -> String {
+    let reason = match code {
+        100 => "Trying",
+        180 => "Ringing",
+        183 => "Session Progress",
+        200 => "OK",
+        404 => "Not Found",
+        408 => "Request Timeout",
+        480 => "Temporarily Unavailable",
+        486 => "Busy Here",
+        487 => "Request Terminated",
+        603 => "Decline",
+        100..=199 => "Progress",
+        200..=299 => "Success",
+        _ => "Failure",
+    };
+
+    format!("SIP/2.0 {} {}\r\n", code, reason)
+}
+
+/// Collects the values of Record-Route headers, one entry per route
+pub fn get_route_set(headers: Vec<&rsip::Header>) -> Vec<String> {
+    headers
+        .iter()
+        .filter_map(|header| match header {
+            rsip::Header::RecordRoute(rr) => Some(rr.value().to_string()),
+            _ => None,
+        })
+        .flat_map(|value| {
+            value
+                .split(',')
+                .map(|route| route.trim().to_string())
+                .collect::<Vec<String>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::helper::{
+        get_address_from_contact, get_answer_direction, get_contact, get_dial_uri, get_fake_sdp,
+        get_offer_direction, get_route_set, get_sdp_direction, get_sdp_media,
+        get_sdp_with_direction, get_tag, get_telephone_event_payload, get_via_host, get_via_port,
+        is_sip_uri,
+    };
+    use crate::config::Transport;
+    use rsip::headers::{ToTypedHeader, UntypedHeader};
+
+    #[test]
+    fn dial_uri() {
+        assert_eq!(
+            get_dial_uri("1005", "sip.server.com", "5060", Transport::Udp),
+            "sip:1005@sip.server.com:5060"
+        );
+        assert_eq!(
+            get_dial_uri("1005", "sip.server.com", "5061", Transport::Tls),
+            "sips:1005@sip.server.com:5061"
+        );
+        assert_eq!(
+            get_dial_uri(
+                "<sips:1004@10.0.0.2:5061?Replaces=a%40b%3Bto-tag%3D1%3Bfrom-tag%3D2>",
+                "sip.server.com",
+                "5060",
+                Transport::Udp
+            ),
+            "sips:1004@10.0.0.2:5061"
+        );
+    }
+
+    #[test]
+    fn media_from_sdp() {
+        let sdp = get_fake_sdp("10.0.0.1");
+        assert!(sdp.contains("o=tggVCE 226678890 391916715 IN IP4 10.0.0.1\r\n"));
+        assert_eq!(
+            get_sdp_media(sdp.as_bytes()),
+            Some(("10.0.0.1".to_string(), 49152))
+        );
+        assert_eq!(get_telephone_event_payload(sdp.as_bytes()), Some(96));
+
+        let sdp = get_fake_sdp("2001:db8::1");
+        assert!(sdp.contains("c=IN IP6 2001:db8::1\r\n"));
+        assert_eq!(
+            get_sdp_media(sdp.as_bytes()),
+            Some(("2001:db8::1".to_string(), 49152))
+        );
+    }
+
+    #[test]
+    fn contact_address() {
+        assert_eq!(
+            get_address_from_contact("\"sipgate\" <sips:1001@10.0.0.1:5061>".to_string()),
+            ("1001@10.0.0.1".to_string(), 5061)
+        );
+        assert_eq!(
+            get_address_from_contact("<sip:1001@10.0.0.1:5060>".to_string()),
+            ("1001@10.0.0.1".to_string(), 5060)
+        );
+        assert_eq!(
+            get_address_from_contact("<sip:1001@[2001:db8::1]:5062;transport=udp>".to_string()),
+            ("1001@[2001:db8::1]".to_string(), 5062)
+        );
+        assert_eq!(
+            get_address_from_contact("<sip:1001@[2001:db8::1]>".to_string()),
+            ("1001@[2001:db8::1]".to_string(), 5060)
+        );
+
+        let contact = get_contact("1001", "1001", "10.0.0.1", "443", Transport::Ws).to_string();
+        assert!(contact.contains(".invalid;transport=ws"));
+        assert!(!contact.contains("10.0.0.1"));
+
+        let contact = get_contact("1001", "1001", "10.0.0.1", "5061", Transport::Tls).to_string();
+        assert!(contact.contains("sips:1001@10.0.0.1:5061;transport=tls"));
+    }
+
+    #[test]
+    fn via_port() {
+        let via = rsip::headers::Via::new("SIP/2.0/UDP 10.0.0.1:5070;branch=z9hG4bK1");
+        assert_eq!(get_via_port(&via.typed().unwrap()), 5070);
+
+        let via = rsip::headers::Via::new("SIP/2.0/UDP 10.0.0.1;branch=z9hG4bK1");
+        assert_eq!(get_via_port(&via.typed().unwrap()), 5060);
+
+        let via = rsip::headers::Via::new("SIP/2.0/TLS 10.0.0.1;branch=z9hG4bK1");
+        assert_eq!(get_via_port(&via.typed().unwrap()), 5061);
+
+        // behind a NAT, the address and port the request came from win
+        let via = rsip::headers::Via::new(
+            "SIP/2.0/UDP 192.168.1.2:5070;rport=40123;received=203.0.113.7;branch=z9hG4bK1",
+        );
+        let via = via.typed().unwrap();
+        assert_eq!(get_via_host(&via), "203.0.113.7");
+        assert_eq!(get_via_port(&via), 40123);
+
+        let via = rsip::headers::Via::new("SIP/2.0/UDP 192.168.1.2:5070;rport;branch=z9hG4bK1");
+        let via = via.typed().unwrap();
+        assert_eq!(get_via_host(&via), "192.168.1.2");
+        assert_eq!(get_via_port(&via), 5070);
+    }
+
+    #[test]
+    fn answer_direction() {
+        let offer = |direction: &str| get_sdp_with_direction("10.0.0.1", 1, direction);
+
+        assert_eq!(
+            get_answer_direction(offer("sendonly").as_bytes(), false),
+            "recvonly"
+        );
+        assert_eq!(
+            get_answer_direction(offer("recvonly").as_bytes(), false),
+            "sendonly"
+        );
+        assert_eq!(
+            get_answer_direction(offer("inactive").as_bytes(), false),
+            "inactive"
+        );
+        assert_eq!(
+            get_answer_direction(get_fake_sdp("10.0.0.1").as_bytes(), false),
+            "sendrecv"
+        );
+    }
+
+    #[test]
+    fn answer_direction_on_hold() {
+        let offer = |direction: &str| get_sdp_with_direction("10.0.0.1", 1, direction);
+
+        assert_eq!(
+            get_answer_direction(offer("sendrecv").as_bytes(), true),
+            "sendonly"
+        );
+        assert_eq!(
+            get_answer_direction(offer("sendonly").as_bytes(), true),
+            "inactive"
+        );
+        assert_eq!(
+            get_answer_direction(offer("recvonly").as_bytes(), true),
+            "sendonly"
+        );
+        assert_eq!(
+            get_answer_direction(offer("inactive").as_bytes(), true),
+            "inactive"
+        );
+    }
+
+    #[test]
+    fn hold_direction() {
+        assert_eq!(get_offer_direction(false, false), "sendrecv");
+        assert_eq!(get_offer_direction(true, false), "sendonly");
+        assert_eq!(get_offer_direction(false, true), "recvonly");
+        assert_eq!(get_offer_direction(true, true), "inactive");
+
+        let offer = |direction: &str| get_sdp_with_direction("10.0.0.1", 1, direction);
+        assert_eq!(get_sdp_direction(offer("sendonly").as_bytes()), "sendonly");
+        assert_eq!(get_sdp_direction(offer("inactive").as_bytes()), "inactive");
+        assert_eq!(
+            get_sdp_direction(get_fake_sdp("10.0.0.1").as_bytes()),
+            "sendrecv"
+        );
+    }
+
+    #[test]
+    fn tag_from_params() {
+        let params = vec![
+            rsip::Param::Other("lr".into(), None),
+            rsip::Param::Tag(rsip::param::Tag::new("a73kszlfl")),
+        ];
+        assert_eq!(get_tag(&params), Some("a73kszlfl".to_string()));
+        assert_eq!(get_tag(&[]), None);
+    }
+
+    #[test]
+    fn route_set_splits_combined_headers() {
+        let first = rsip::Header::RecordRoute(rsip::headers::RecordRoute::new(
+            "<sip:p1.example.com;lr>, <sip:p2.example.com;lr>",
+        ));
+        let second =
+            rsip::Header::RecordRoute(rsip::headers::RecordRoute::new("<sip:p3.example.com;lr>"));
+
+        assert_eq!(
+            get_route_set(vec![&first, &second]),
+            vec![
+                "<sip:p1.example.com;lr>".to_string(),
+                "<sip:p2.example.com;lr>".to_string(),
+                "<sip:p3.example.com;lr>".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn sip_uri_or_number() {
+        assert!(is_sip_uri("sip:1002@sip.server.com"));
+        assert!(is_sip_uri("sips:1002@sip.server.com"));
+        assert!(!is_sip_uri("1002"));
+    }
+}
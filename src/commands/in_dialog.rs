@@ -0,0 +1,90 @@
+use rsip::headers::{UntypedHeader, UserAgent};
+use rsip::{Header, Method, SipMessage};
+
+use crate::state::{dialogs::Dialog, options::SipOptions};
+
+use super::helper::{get_contact, get_uri, get_via};
+
+impl SipOptions {
+    /// Composes a request within an established dialog, addressed to the remote target
+    /// through the route set, `body` is the content type along with the content
+    pub fn create_in_dialog_request(
+        &self,
+        dialog: &Dialog,
+        method: Method,
+        cseq: u32,
+        extra_headers: Vec<Header>,
+        body: Option<(&str, Vec<u8>)>,
+    ) -> SipMessage {
+        let mut headers: rsip::Headers = Default::default();
+
+        headers.push(get_via(&self.ip, &self.local_port, self.transport));
+        headers.push(rsip::headers::MaxForwards::from(70).into());
+        headers.push(
+            rsip::typed::From {
+                display_name: None,
+                uri: get_uri(dialog.local_uri.as_deref().unwrap_or_default()),
+                params: vec![rsip::Param::Tag(rsip::param::Tag::new(&dialog.local_tag))],
+            }
+            .into(),
+        );
+        headers.push(
+            rsip::typed::To {
+                display_name: None,
+                uri: get_uri(dialog.remote_uri.as_deref().unwrap_or_default()),
+                params: match &dialog.remote_tag {
+                    Some(tag) => vec![rsip::Param::Tag(rsip::param::Tag::new(tag))],
+                    None => vec![],
+                },
+            }
+            .into(),
+        );
+        headers.push(rsip::headers::CallId::from(dialog.call_id.as_str()).into());
+        headers.push(rsip::typed::CSeq { seq: cseq, method }.into());
+
+        for route in dialog.route_set.iter() {
+            headers.push(Header::Route(rsip::headers::Route::new(route.to_string())));
+        }
+
+        headers.push(get_contact(
+            &self.username,
+            &self.extension,
+            &self.ip,
+            &self.local_port,
+            self.transport,
+        ));
+
+        for header in extra_headers {
+            headers.push(header);
+        }
+
+        headers.push(Header::UserAgent(UserAgent::new("Tiggy")));
+
+        let body = match body {
+            Some((content_type, content)) => {
+                headers.push(rsip::headers::ContentType::from(content_type).into());
+                content
+            }
+            None => vec![],
+        };
+        headers.push(rsip::headers::ContentLength::from(body.len().to_string()).into());
+
+        let request: SipMessage = rsip::Request {
+            method,
+            uri: get_uri(
+                dialog
+                    .remote_target
+                    .as_ref()
+                    .or(dialog.remote_uri.as_ref())
+                    .map(|uri| uri.as_str())
+                    .unwrap_or_default(),
+            ),
+            version: rsip::Version::V2,
+            headers,
+            body,
+        }
+        .into();
+
+        request
+    }
+}
@@ -1,29 +1,42 @@
 use crate::composer::header_extension::PartialHeaderClone;
 use crate::state::options::SipOptions;
 use rsip::headers::{UntypedHeader, UserAgent};
-use rsip::{headers::auth, Header, SipMessage, StatusCode};
+use rsip::{headers::auth, message::HasHeaders, Header, SipMessage, StatusCode};
 
-use super::helper::{get_base_uri, get_contact, get_fake_sdp, get_from, get_to, get_via};
+use super::helper::{
+    get_base_uri, get_contact, get_dial_uri, get_fake_sdp, get_from, get_uri, get_via, MIN_SE,
+    SESSION_EXPIRES,
+};
+use crate::parsers::{get_min_se, get_uri_replaces, get_user_from_uri};
 
 impl SipOptions {
     pub fn set_initial_invite(&self) -> SipMessage {
         let mut headers: rsip::Headers = Default::default();
         let base_uri = get_base_uri(&self.extension, &self.sip_server, &self.sip_port);
-
-        headers.push(get_via(&self.ip, &self.sip_port));
-        headers.push(get_from(&self.username, &self.tag_local, base_uri));
-        headers.push(get_to(
-            self.cld.as_ref().unwrap(),
+        let dial_uri = get_dial_uri(
             self.cld.as_ref().unwrap(),
             &self.sip_server,
             &self.sip_port,
-        ));
+            self.transport,
+        );
+
+        headers.push(get_via(&self.ip, &self.local_port, self.transport));
+        headers.push(get_from(&self.username, &self.tag_local, base_uri));
+        headers.push(
+            rsip::typed::To {
+                display_name: get_user_from_uri(&dial_uri),
+                uri: get_uri(&dial_uri),
+                params: Default::default(),
+            }
+            .into(),
+        );
         headers.push(rsip::headers::CallId::from(self.call_id.as_str()).into());
         headers.push(get_contact(
             &self.username,
             &self.extension,
-            &self.sip_server,
-            &self.sip_port,
+            &self.ip,
+            &self.local_port,
+            self.transport,
         ));
         headers.push(rsip::headers::MaxForwards::from(70).into());
         headers.push(
@@ -40,6 +53,12 @@ impl SipOptions {
             .into(),
         );
 
+        for header in get_session_timer_offer(MIN_SE) {
+            headers.push(header);
+        }
+        if let Some(replaces) = get_replaces(self.cld.as_ref().unwrap()) {
+            headers.push(replaces);
+        }
         headers.push(Header::UserAgent(UserAgent::new("Tiggy")));
 
         let fake_sdp_body = get_fake_sdp(&self.ip);
@@ -49,17 +68,7 @@ impl SipOptions {
 
         let response: SipMessage = rsip::Request {
             method: rsip::Method::Invite,
-            uri: rsip::Uri {
-                scheme: Some(rsip::Scheme::Sip),
-                host_with_port: rsip::Domain::from(format!(
-                    "{}@{}:{}",
-                    &self.cld.as_ref().unwrap(),
-                    &self.sip_server,
-                    &self.sip_port
-                ))
-                .into(),
-                ..Default::default()
-            },
+            uri: get_uri(&dial_uri),
             version: rsip::Version::V2,
             headers,
             body: fake_sdp_body.as_bytes().to_vec(),
@@ -77,7 +86,25 @@ impl SipOptions {
             .as_ref()
             .unwrap()
             .partial_header_clone(false, false);
+        let dial_uri = get_dial_uri(
+            self.cld.as_ref().unwrap(),
+            &self.sip_server,
+            &self.sip_port,
+            self.transport,
+        );
 
+        // a retry after a 422 keeps the Min-SE it asked for
+        let min_se = self
+            .msg
+            .as_ref()
+            .and_then(|msg| get_min_se(msg.headers()))
+            .unwrap_or(MIN_SE);
+        for header in get_session_timer_offer(min_se) {
+            headers.push(header);
+        }
+        if let Some(replaces) = get_replaces(self.cld.as_ref().unwrap()) {
+            headers.push(replaces);
+        }
         headers.push(rsip::headers::ContentType::from("application/sdp").into());
 
         let auth = rsip::typed::Authorization {
@@ -85,17 +112,7 @@ impl SipOptions {
             username: self.username.to_string(),
             realm: self.sip_server.to_string(),
             nonce: self.nonce.as_ref().unwrap().to_string(),
-            uri: rsip::Uri {
-                scheme: Some(rsip::Scheme::Sip),
-                host_with_port: rsip::Domain::from(format!(
-                    "{}{}:{}",
-                    format!("{}@", &self.cld.as_ref().unwrap_or(&"".to_string())),
-                    &self.sip_server,
-                    &self.sip_port
-                ))
-                .into(),
-                ..Default::default()
-            },
+            uri: get_uri(&dial_uri),
             response: self.md5.as_ref().unwrap().to_string(),
             algorithm: Some(auth::Algorithm::Md5),
             opaque: None,
@@ -117,17 +134,7 @@ impl SipOptions {
 
         let request: SipMessage = rsip::Request {
             method: rsip::Method::Invite,
-            uri: rsip::Uri {
-                scheme: Some(rsip::Scheme::Sip),
-                host_with_port: rsip::Domain::from(format!(
-                    "{}@{}:{}",
-                    self.cld.as_ref().unwrap(),
-                    &self.sip_server,
-                    &self.sip_port
-                ))
-                .into(),
-                ..Default::default()
-            },
+            uri: get_uri(&dial_uri),
             version: rsip::Version::V2,
             headers: headers.clone(),
             body: self.msg.as_ref().unwrap().body().clone(),
@@ -137,3 +144,80 @@ impl SipOptions {
         request
     }
 }
+
+impl SipOptions {
+    /// The INVITE a 422 (RFC 4028) turned down, again with the Min-SE the 422 asked for
+    pub fn push_min_se_to_invite(&self, min_se: u32) -> SipMessage {
+        let headers = &mut self
+            .msg
+            .as_ref()
+            .unwrap()
+            .partial_header_clone(false, false);
+        let dial_uri = get_dial_uri(
+            self.cld.as_ref().unwrap(),
+            &self.sip_server,
+            &self.sip_port,
+            self.transport,
+        );
+
+        for header in get_session_timer_offer(min_se) {
+            headers.push(header);
+        }
+        if let Some(replaces) = get_replaces(self.cld.as_ref().unwrap()) {
+            headers.push(replaces);
+        }
+        headers.push(rsip::headers::ContentType::from("application/sdp").into());
+
+        rsip::Request {
+            method: rsip::Method::Invite,
+            uri: get_uri(&dial_uri),
+            version: rsip::Version::V2,
+            headers: headers.clone(),
+            body: self.msg.as_ref().unwrap().body().clone(),
+        }
+        .into()
+    }
+}
+
+/// Replaces header of a call on behalf of an attended transfer, it takes over
+/// the call it replaces, every retry of the INVITE carries it again
+fn get_replaces(cld: &str) -> Option<Header> {
+    get_uri_replaces(cld).map(|replaces| Header::Other("Replaces".into(), replaces))
+}
+
+/// Extensions an INVITE of ours supports and the session timer (RFC 4028) it asks for,
+/// never below `min_se`, the refresher is left to the other side
+fn get_session_timer_offer(min_se: u32) -> Vec<Header> {
+    vec![
+        Header::Other("Supported".into(), "100rel, timer".into()),
+        Header::Other(
+            "Session-Expires".into(),
+            std::cmp::max(SESSION_EXPIRES, min_se).to_string(),
+        ),
+        Header::Other("Min-SE".into(), min_se.to_string()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::{options::SipOptions, transactions::tests::options};
+    use rsip::{message::HasHeaders, SipMessage};
+
+    #[test]
+    fn retries_keep_replaces() {
+        let mut invite = SipOptions {
+            cld: Some("<sip:1004@10.0.0.2?Replaces=a%40b%3Bto-tag%3D1%3Bfrom-tag%3D2>".to_string()),
+            ..options()
+        };
+        invite.msg = Some(invite.set_initial_invite());
+        let retry: SipMessage = invite.push_min_se_to_invite(1800);
+
+        let replaces: Vec<String> = retry
+            .headers()
+            .iter()
+            .map(|header| header.to_string())
+            .filter(|header| header.starts_with("Replaces:"))
+            .collect();
+        assert_eq!(replaces, vec!["Replaces: a@b;to-tag=1;from-tag=2"]);
+    }
+}
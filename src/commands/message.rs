@@ -0,0 +1,68 @@
+use crate::composer::header_extension::PartialHeaderClone;
+use crate::state::options::SipOptions;
+use rsip::headers::{UntypedHeader, UserAgent};
+use rsip::{Header, SipMessage, StatusCode};
+
+use super::helper::{get_base_uri, get_from, get_uri, get_via};
+
+impl SipOptions {
+    /// Page mode MESSAGE (RFC 3428) to `cld`, a full `sip:` uri
+    pub fn create_message(&self, text: &str) -> SipMessage {
+        let mut headers: rsip::Headers = Default::default();
+        let base_uri = get_base_uri(&self.extension, &self.sip_server, &self.sip_port);
+        let target = get_uri(self.cld.as_ref().unwrap());
+
+        headers.push(get_via(&self.ip, &self.local_port, self.transport));
+        headers.push(get_from(&self.username, &self.tag_local, base_uri));
+        headers.push(
+            rsip::typed::To {
+                display_name: None,
+                uri: target.clone(),
+                params: Default::default(),
+            }
+            .into(),
+        );
+        headers.push(rsip::headers::CallId::from(self.call_id.as_str()).into());
+        headers.push(rsip::headers::MaxForwards::from(70).into());
+        headers.push(
+            rsip::typed::CSeq {
+                seq: 1,
+                method: rsip::Method::Message,
+            }
+            .into(),
+        );
+        headers.push(Header::UserAgent(UserAgent::new("Tiggy")));
+        headers.push(rsip::headers::ContentType::from("text/plain").into());
+        headers.push(rsip::headers::ContentLength::from(text.len().to_string()).into());
+
+        rsip::Request {
+            method: rsip::Method::Message,
+            uri: target,
+            version: rsip::Version::V2,
+            headers,
+            body: text.as_bytes().to_vec(),
+        }
+        .into()
+    }
+
+    /// Repeats the MESSAGE in `msg` with the credentials `set_auth` computed
+    pub fn push_auth_to_message(&self, code: StatusCode) -> SipMessage {
+        let headers = &mut self
+            .msg
+            .as_ref()
+            .unwrap()
+            .partial_header_clone(false, false);
+
+        headers.push(rsip::headers::ContentType::from("text/plain").into());
+        headers.push(self.get_auth_header(&code));
+
+        rsip::Request {
+            method: rsip::Method::Message,
+            uri: get_uri(self.cld.as_ref().unwrap()),
+            version: rsip::Version::V2,
+            headers: headers.clone(),
+            body: self.msg.as_ref().unwrap().body().clone(),
+        }
+        .into()
+    }
+}
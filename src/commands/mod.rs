@@ -2,13 +2,35 @@
 pub mod ack;
 /// Inserts Auth headers
 pub mod auth;
+/// Composes a BYE
+pub mod bye;
+/// Composes a CANCEL
+pub mod cancel;
 /// Various helper functions to extract information from a SIP Message or its Headers
 pub mod helper;
+/// Composes requests within an established dialog
+pub mod in_dialog;
 /// Composes an Invite
 pub mod invite;
+/// Composes a MESSAGE
+pub mod message;
+/// Headers of a NOTIFY
+pub mod notify;
 /// Composes an OK
 pub mod ok;
+/// Composes a PRACK
+pub mod prack;
+/// Headers of a REFER
+pub mod refer;
 /// Composes a REGISTER
 pub mod register;
+/// Composes a final response without a body
+pub mod reject;
+/// Composes a RINGING
+pub mod ringing;
+/// Composes a SUBSCRIBE
+pub mod subscribe;
 /// Composes a TRYING
 pub mod trying;
+/// Headers of an UPDATE or a re-INVITE
+pub mod update;
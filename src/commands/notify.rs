@@ -0,0 +1,9 @@
+use rsip::Header;
+
+/// Headers of a NOTIFY within an established dialog for the `event` package
+pub fn get_notify_headers(event: &str, subscription_state: &str) -> Vec<Header> {
+    vec![
+        Header::Other("Event".into(), event.to_string()),
+        Header::Other("Subscription-State".into(), subscription_state.to_string()),
+    ]
+}
@@ -6,22 +6,50 @@ use rsip::Request;
 use rsip::{message::HeadersExt, Header, SipMessage};
 use rsip::{Method, Param};
 
-use super::helper::{get_base_uri, get_fake_sdp, get_remote_tag};
+use uuid::Uuid;
 
+use super::helper::{get_contact, get_fake_sdp, get_tag};
+use crate::parsers::{has_option_tag, negotiate_session_timer};
+
+/// `local_tag` is the To tag of a dialog created by this response,
+/// requests inside a dialog already carry theirs
 pub fn ok(
     conf: &JSONConfiguration,
     ip: &str,
     req: &Request,
     method: Method,
     sdp: bool,
+    local_tag: Option<&str>,
 ) -> rsip::SipMessage {
-    let mut headers: rsip::Headers = Default::default();
-    let base_uri = get_base_uri(
-        &conf.extension,
-        &conf.sip_server,
-        &conf.sip_port.to_string(),
-    );
+    let body = match sdp {
+        true => get_fake_sdp(ip),
+        false => String::new(),
+    };
+
+    ok_with_body(conf, ip, req, method, &body, local_tag)
+}
+
+/// OK carrying `sdp`, the answer to an offer made within a dialog
+pub fn ok_with_sdp(
+    conf: &JSONConfiguration,
+    ip: &str,
+    req: &Request,
+    method: Method,
+    sdp: &str,
+) -> rsip::SipMessage {
+    ok_with_body(conf, ip, req, method, sdp, None)
+}
 
+/// OK with `sdp` as its body, an empty one leaves out the Content-Type
+fn ok_with_body(
+    conf: &JSONConfiguration,
+    ip: &str,
+    req: &Request,
+    method: Method,
+    sdp: &str,
+    local_tag: Option<&str>,
+) -> rsip::SipMessage {
+    let mut headers: rsip::Headers = Default::default();
     headers.push_many(req.headers.get_via_header_array());
     headers.push_many(req.headers.get_record_route_header_array());
 
@@ -32,26 +60,30 @@ pub fn ok(
     headers.push(req.from_header().unwrap().clone().into());
     let to = req.to_header().unwrap().typed().unwrap();
     let cseq = req.cseq_header().unwrap().typed().unwrap();
+    let session_timer = match method {
+        Method::Invite | Method::Update => negotiate_session_timer(&req.headers),
+        _ => None,
+    };
 
-    let hstr = req.clone().from_header().unwrap().to_string();
-    let remote_tag = get_remote_tag(&hstr);
+    let to_tag = get_tag(&to.params)
+        .or_else(|| local_tag.map(|tag| tag.to_string()))
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
 
     headers.push(
         rsip::typed::To {
             display_name: to.display_name.clone(),
             uri: to.uri,
-            params: vec![Param::Tag(Tag::new(remote_tag))],
-        }
-        .into(),
-    );
-    headers.push(
-        rsip::typed::Contact {
-            display_name: Some(conf.username.to_string()),
-            uri: base_uri,
-            params: Default::default(),
+            params: vec![Param::Tag(Tag::new(to_tag))],
         }
         .into(),
     );
+    headers.push(get_contact(
+        &conf.username,
+        &conf.extension,
+        ip,
+        &conf.local_sip_port.to_string(),
+        conf.transport,
+    ));
     headers.push(req.call_id_header().unwrap().clone().into());
     headers.push(
         rsip::typed::CSeq {
@@ -63,26 +95,105 @@ pub fn ok(
     headers.push(Header::Allow(Allow::new(
         "ACK,BYE,CANCEL,INFO,INVITE,NOTIFY,OPTIONS,PRACK,REFER,UPDATE",
     )));
+    if let Some((interval, refresher)) = session_timer {
+        if has_option_tag(&req.headers, "Supported", "timer") {
+            headers.push(Header::Other("Require".into(), "timer".into()));
+        }
+        headers.push(Header::Other(
+            "Session-Expires".into(),
+            format!("{};refresher={}", interval, refresher),
+        ));
+    }
     headers.push(Header::UserAgent(UserAgent::new("Tiggy")));
-    headers.push(Header::ContentType(ContentType::new("application/sdp")));
-
-    let fake_sdp_body = get_fake_sdp(ip);
-
+    if !sdp.is_empty() {
+        headers.push(Header::ContentType(ContentType::new("application/sdp")));
+    }
     headers.push(Header::ContentLength(ContentLength::new(
-        fake_sdp_body.len().to_string(),
+        sdp.len().to_string(),
     )));
 
     let response: SipMessage = rsip::Response {
         status_code: rsip::StatusCode::OK,
         version: rsip::Version::V2,
         headers,
-        body: if sdp {
-            fake_sdp_body.as_bytes().to_vec()
-        } else {
-            Default::default()
-        },
+        body: sdp.as_bytes().to_vec(),
     }
     .into();
 
     response
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ok, ok_with_sdp};
+    use crate::config::{JSONConfiguration, Transport};
+    use rsip::{message::HasHeaders, Method, Request, SipMessage};
+    use std::convert::TryFrom;
+
+    fn conf() -> JSONConfiguration {
+        JSONConfiguration {
+            username: "1004".to_string(),
+            password: "it_doesnt_matter".to_string(),
+            sip_server: "sip.server.com".to_string(),
+            sip_port: 5060,
+            extension: "1004".to_string(),
+            pcap: None,
+            reg_timeout: 120,
+            answer: Default::default(),
+            mwi: None,
+            blf: vec![],
+            transport: Transport::Udp,
+            tls: Default::default(),
+            ws_path: None,
+            local_ip: None,
+            local_sip_port: 5060,
+            interface: None,
+        }
+    }
+
+    fn bye() -> Request {
+        match SipMessage::try_from(
+            "BYE sip:1004@10.0.0.1 SIP/2.0\r\nVia: SIP/2.0/UDP 10.0.0.2:5060;branch=z9hG4bK1\r\nMax-Forwards: 70\r\nFrom: <sip:1002@10.0.0.2>;tag=theirs\r\nTo: <sip:1004@10.0.0.1>;tag=ours\r\nCall-ID: call\r\nCSeq: 2 BYE\r\nContent-Length: 0\r\n\r\n"
+                .to_string(),
+        )
+        .unwrap()
+        {
+            SipMessage::Request(request) => request,
+            SipMessage::Response(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn headers_follow_the_body() {
+        let headers = |response: SipMessage| -> Vec<String> {
+            response
+                .headers()
+                .iter()
+                .map(|header| header.to_string())
+                .filter(|header| header.starts_with("Content-"))
+                .collect()
+        };
+
+        let without_sdp = ok(&conf(), "10.0.0.1", &bye(), Method::Bye, false, None);
+        assert!(without_sdp.body().is_empty());
+        assert_eq!(headers(without_sdp), vec!["Content-Length: 0"]);
+
+        let with_sdp = ok(&conf(), "10.0.0.1", &bye(), Method::Invite, true, None);
+        let length = format!("Content-Length: {}", with_sdp.body().len());
+        assert_eq!(
+            headers(with_sdp),
+            vec!["Content-Type: application/sdp".to_string(), length]
+        );
+    }
+
+    #[test]
+    fn answer_has_our_contact() {
+        let answer = ok_with_sdp(&conf(), "10.0.0.1", &bye(), Method::Invite, "v=0\r\n");
+
+        assert!(answer
+            .headers()
+            .iter()
+            .map(|header| header.to_string())
+            .any(|header| header.starts_with("Contact:") && header.contains("@10.0.0.1:5060")));
+    }
+}
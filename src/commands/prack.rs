@@ -0,0 +1,26 @@
+use rsip::{Header, Method, SipMessage};
+
+use crate::state::{dialogs::Dialog, options::SipOptions};
+
+impl SipOptions {
+    /// PRACK for the reliable provisional response `rseq`,
+    /// sent to the INVITE with CSeq `invite_cseq`
+    pub fn create_prack(
+        &self,
+        dialog: &Dialog,
+        cseq: u32,
+        rseq: u32,
+        invite_cseq: u32,
+    ) -> SipMessage {
+        self.create_in_dialog_request(
+            dialog,
+            Method::PRack,
+            cseq,
+            vec![Header::Other(
+                "RAck".into(),
+                format!("{} {} INVITE", rseq, invite_cseq),
+            )],
+            None,
+        )
+    }
+}
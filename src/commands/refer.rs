@@ -0,0 +1,14 @@
+use rsip::Header;
+
+use crate::state::dialogs::Dialog;
+
+/// Headers of a REFER within an established dialog, asking the other side to call `refer_to`
+pub fn get_refer_headers(dialog: &Dialog, refer_to: &str) -> Vec<Header> {
+    vec![
+        Header::Other("Refer-To".into(), format!("<{}>", refer_to)),
+        Header::Other(
+            "Referred-By".into(),
+            format!("<{}>", dialog.local_uri.as_deref().unwrap_or_default()),
+        ),
+    ]
+}
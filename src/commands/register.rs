@@ -3,7 +3,7 @@ use crate::state::options::SipOptions;
 use rsip::headers::{Allow, UntypedHeader, UserAgent};
 use rsip::{headers::auth, Header, SipMessage, StatusCode};
 
-use super::helper::{get_base_uri, get_contact, get_from, get_to, get_via};
+use super::helper::{get_base_uri, get_contact, get_from, get_host, get_to, get_via};
 
 impl SipOptions {
     pub fn set_initial_register(&self) -> SipMessage {
@@ -12,7 +12,7 @@ impl SipOptions {
         let base_uri = get_base_uri(&self.extension, &self.sip_server, &self.sip_port);
 
         headers.push(rsip::headers::Expires::from(120).into());
-        headers.push(get_via(&self.ip, &self.sip_port));
+        headers.push(get_via(&self.ip, &self.local_port, self.transport));
         headers.push(get_from(&self.username, &self.tag_local, base_uri));
         headers.push(get_to(
             &self.username,
@@ -25,7 +25,8 @@ impl SipOptions {
             &self.username,
             &self.username,
             &self.ip,
-            &self.sip_port,
+            &self.local_port,
+            self.transport,
         ));
         headers.push(rsip::headers::MaxForwards::from(70).into());
         headers.push(
@@ -44,8 +45,8 @@ impl SipOptions {
         let request: SipMessage = rsip::Request {
             method: rsip::Method::Register,
             uri: rsip::Uri {
-                scheme: Some(rsip::Scheme::Sip),
-                host_with_port: rsip::Domain::from(format!("{}", &self.sip_server)).into(),
+                scheme: Some(self.transport.scheme()),
+                host_with_port: rsip::Domain::from(get_host(&self.sip_server)).into(),
                 ..Default::default()
             },
             version: rsip::Version::V2,
@@ -64,8 +65,8 @@ impl SipOptions {
         let request: SipMessage = rsip::Request {
             method: rsip::Method::Register,
             uri: rsip::Uri {
-                scheme: Some(rsip::Scheme::Sip),
-                host_with_port: rsip::Domain::from(format!("{}", &self.sip_server)).into(),
+                scheme: Some(self.transport.scheme()),
+                host_with_port: rsip::Domain::from(get_host(&self.sip_server)).into(),
                 ..Default::default()
             },
             version: rsip::Version::V2,
@@ -88,8 +89,8 @@ impl SipOptions {
         let request: SipMessage = rsip::Request {
             method: rsip::Method::Register,
             uri: rsip::Uri {
-                scheme: Some(rsip::Scheme::Sip),
-                host_with_port: rsip::Domain::from(format!("{}", &self.sip_server)).into(),
+                scheme: Some(self.transport.scheme()),
+                host_with_port: rsip::Domain::from(get_host(&self.sip_server)).into(),
                 ..Default::default()
             },
             version: rsip::Version::V2,
@@ -119,7 +120,9 @@ impl SipOptions {
                 scheme: Some(rsip::Scheme::Sip),
                 host_with_port: rsip::Domain::from(format!(
                     "{}@{}:{}",
-                    &self.extension, &self.sip_server, &self.sip_port
+                    &self.extension,
+                    get_host(&self.sip_server),
+                    &self.sip_port
                 ))
                 .into(),
                 ..Default::default()
@@ -154,8 +157,8 @@ impl SipOptions {
         let request: SipMessage = rsip::Request {
             method: rsip::Method::Register,
             uri: rsip::Uri {
-                scheme: Some(rsip::Scheme::Sip),
-                host_with_port: rsip::Domain::from(format!("{}", &self.sip_server)).into(),
+                scheme: Some(self.transport.scheme()),
+                host_with_port: rsip::Domain::from(get_host(&self.sip_server)).into(),
                 ..Default::default()
             },
             version: rsip::Version::V2,
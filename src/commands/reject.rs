@@ -0,0 +1,54 @@
+use crate::composer::header_extension::CustomHeaderExtension;
+use rsip::headers::{ToTypedHeader, UntypedHeader, UserAgent};
+use rsip::param::Tag;
+use rsip::{message::HeadersExt, Header, Param, Request, SipMessage, StatusCode};
+
+use uuid::Uuid;
+
+use super::helper::{get_tag, MIN_SE};
+
+/// Composes a final response without a body, for anything that is not a 200 OK,
+/// `local_tag` is the To tag of the dialog the request was sent in,
+/// a 422 carries the Min-SE the request fell short of (RFC 4028)
+pub fn reject(req: &Request, status_code: StatusCode, local_tag: Option<&str>) -> SipMessage {
+    let mut headers: rsip::Headers = Default::default();
+
+    headers.push_many(req.headers.get_via_header_array());
+    headers.push(req.from_header().unwrap().clone().into());
+
+    let to = req.to_header().unwrap().typed().unwrap();
+    let to_tag = get_tag(&to.params)
+        .or_else(|| local_tag.map(|tag| tag.to_string()))
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    headers.push(
+        rsip::typed::To {
+            display_name: to.display_name.clone(),
+            uri: to.uri,
+            params: vec![Param::Tag(Tag::new(to_tag))],
+        }
+        .into(),
+    );
+    headers.push(req.call_id_header().unwrap().clone().into());
+    headers.push(req.cseq_header().unwrap().clone().into());
+    if status_code.code() == 422 {
+        headers.push(Header::Other("Min-SE".into(), MIN_SE.to_string()));
+    }
+    headers.push(Header::UserAgent(UserAgent::new("Tiggy")));
+    headers.push(rsip::headers::ContentLength::default().into());
+
+    let response: SipMessage = rsip::Response {
+        status_code,
+        version: rsip::Version::V2,
+        headers,
+        body: Default::default(),
+    }
+    .into();
+
+    response
+}
+
+/// Composes a 202 Accepted, like the error responses it carries no body
+pub fn accepted(req: &Request) -> SipMessage {
+    reject(req, StatusCode::Accepted, None)
+}
@@ -0,0 +1,61 @@
+use crate::composer::header_extension::CustomHeaderExtension;
+use crate::config::JSONConfiguration;
+use rsip::headers::{Allow, ToTypedHeader, UntypedHeader, UserAgent};
+use rsip::param::Tag;
+use rsip::Request;
+use rsip::{message::HeadersExt, Header, Param, SipMessage};
+
+use super::helper::get_contact;
+
+/// `local_tag` is the To tag of the early dialog created by this response,
+/// with an `rseq` the response is sent reliably (RFC 3262) and needs a PRACK
+pub fn ringing(
+    conf: &JSONConfiguration,
+    ip: &str,
+    req: &Request,
+    local_tag: &str,
+    rseq: Option<u32>,
+) -> rsip::SipMessage {
+    let mut headers: rsip::Headers = Default::default();
+    headers.push_many(req.headers.get_via_header_array());
+    headers.push_many(req.headers.get_record_route_header_array());
+    headers.push(req.from_header().unwrap().clone().into());
+
+    let to = req.to_header().unwrap().typed().unwrap();
+    headers.push(
+        rsip::typed::To {
+            display_name: to.display_name.clone(),
+            uri: to.uri,
+            params: vec![Param::Tag(Tag::new(local_tag))],
+        }
+        .into(),
+    );
+    headers.push(get_contact(
+        &conf.username,
+        &conf.extension,
+        ip,
+        &conf.local_sip_port.to_string(),
+        conf.transport,
+    ));
+    headers.push(req.call_id_header().unwrap().clone().into());
+    headers.push(req.cseq_header().unwrap().clone().into());
+    headers.push(Header::Allow(Allow::new(
+        "ACK,BYE,CANCEL,INFO,INVITE,NOTIFY,OPTIONS,PRACK,REFER,UPDATE",
+    )));
+    if let Some(rseq) = rseq {
+        headers.push(Header::Other("Require".into(), "100rel".into()));
+        headers.push(Header::Other("RSeq".into(), rseq.to_string()));
+    }
+    headers.push(Header::UserAgent(UserAgent::new("Tiggy")));
+    headers.push(rsip::headers::ContentLength::default().into());
+
+    let response: SipMessage = rsip::Response {
+        status_code: rsip::StatusCode::Ringing,
+        version: rsip::Version::V2,
+        headers,
+        body: Default::default(),
+    }
+    .into();
+
+    response
+}
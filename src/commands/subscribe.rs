@@ -0,0 +1,112 @@
+use crate::composer::header_extension::PartialHeaderClone;
+use crate::state::{dialogs::Dialog, options::SipOptions};
+use rsip::headers::{UntypedHeader, UserAgent};
+use rsip::{Header, SipMessage, StatusCode};
+
+use super::helper::{get_base_uri, get_contact, get_from, get_other_header, get_uri, get_via};
+
+impl SipOptions {
+    /// SUBSCRIBE (RFC 6665) to `event` of `cld`, a full `sip:` uri,
+    /// creating a subscription that lasts `expires` seconds
+    pub fn create_subscribe(&self, event: &str, expires: u32) -> SipMessage {
+        let mut headers: rsip::Headers = Default::default();
+        let base_uri = get_base_uri(&self.extension, &self.sip_server, &self.sip_port);
+        let target = get_uri(self.cld.as_ref().unwrap());
+
+        headers.push(get_via(&self.ip, &self.local_port, self.transport));
+        headers.push(get_from(&self.username, &self.tag_local, base_uri));
+        headers.push(
+            rsip::typed::To {
+                display_name: None,
+                uri: target.clone(),
+                params: Default::default(),
+            }
+            .into(),
+        );
+        headers.push(rsip::headers::CallId::from(self.call_id.as_str()).into());
+        headers.push(get_contact(
+            &self.username,
+            &self.extension,
+            &self.ip,
+            &self.local_port,
+            self.transport,
+        ));
+        headers.push(rsip::headers::MaxForwards::from(70).into());
+        headers.push(
+            rsip::typed::CSeq {
+                seq: 1,
+                method: rsip::Method::Subscribe,
+            }
+            .into(),
+        );
+        headers.push(rsip::headers::Expires::from(expires).into());
+        for header in get_package_headers(event) {
+            headers.push(header);
+        }
+        headers.push(Header::UserAgent(UserAgent::new("Tiggy")));
+        headers.push(rsip::headers::ContentLength::default().into());
+
+        rsip::Request {
+            method: rsip::Method::Subscribe,
+            uri: target,
+            version: rsip::Version::V2,
+            headers,
+            body: Default::default(),
+        }
+        .into()
+    }
+
+    /// Refreshes the subscription to `event` of a confirmed dialog, `expires` 0 ends it
+    pub fn create_resubscribe(
+        &self,
+        dialog: &Dialog,
+        event: &str,
+        cseq: u32,
+        expires: u32,
+    ) -> SipMessage {
+        let mut headers = vec![rsip::headers::Expires::from(expires).into()];
+        headers.extend(get_package_headers(event));
+
+        self.create_in_dialog_request(dialog, rsip::Method::Subscribe, cseq, headers, None)
+    }
+
+    /// Repeats the SUBSCRIBE in `msg` with the credentials `set_auth` computed
+    pub fn push_auth_to_subscribe(&self, code: StatusCode) -> SipMessage {
+        let msg = self.msg.as_ref().unwrap();
+        let headers = &mut msg.partial_header_clone(false, false);
+
+        let event = get_other_header(msg.headers(), "Event").unwrap_or_default();
+        for header in get_package_headers(&event) {
+            headers.push(header);
+        }
+        headers.push(self.get_auth_header(&code));
+
+        let uri = match msg {
+            SipMessage::Request(request) => request.uri.clone(),
+            SipMessage::Response(_) => get_uri(self.cld.as_ref().unwrap()),
+        };
+
+        rsip::Request {
+            method: rsip::Method::Subscribe,
+            uri,
+            version: rsip::Version::V2,
+            headers: headers.clone(),
+            body: Default::default(),
+        }
+        .into()
+    }
+}
+
+/// Event and the Accept of the bodies its NOTIFYs carry
+fn get_package_headers(event: &str) -> Vec<Header> {
+    let accept = match event {
+        "message-summary" => "application/simple-message-summary",
+        "dialog" => "application/dialog-info+xml",
+        _ => "*/*",
+    };
+
+    vec![
+        Header::Other("Event".into(), event.into()),
+        rsip::headers::Accept::new(accept).into(),
+    ]
+}
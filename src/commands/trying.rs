@@ -1,11 +1,12 @@
+use crate::composer::header_extension::CustomHeaderExtension;
 use crate::config::JSONConfiguration;
 use rsip::headers::{Allow, UntypedHeader, UserAgent};
 use rsip::Request;
 use rsip::{message::HeadersExt, Header, SipMessage};
 
-use super::helper::{get_base_uri, get_via};
+use super::helper::get_base_uri;
 
-pub fn trying(conf: &JSONConfiguration, ip: &str, req: &Request) -> rsip::SipMessage {
+pub fn trying(conf: &JSONConfiguration, req: &Request) -> rsip::SipMessage {
     let mut headers: rsip::Headers = Default::default();
     let base_uri = get_base_uri(
         &conf.extension,
@@ -13,7 +14,7 @@ pub fn trying(conf: &JSONConfiguration, ip: &str, req: &Request) -> rsip::SipMes
         &conf.sip_port.to_string(),
     );
 
-    headers.push(get_via(ip, &conf.sip_port.to_string()));
+    headers.push_many(req.headers.get_via_header_array());
     headers.push(req.max_forwards_header().unwrap().clone().into());
     headers.push(req.from_header().unwrap().clone().into());
     headers.push(req.to_header().unwrap().clone().into());
@@ -26,13 +27,7 @@ pub fn trying(conf: &JSONConfiguration, ip: &str, req: &Request) -> rsip::SipMes
         .into(),
     );
     headers.push(req.call_id_header().unwrap().clone().into());
-    headers.push(
-        rsip::typed::CSeq {
-            seq: 1,
-            method: rsip::Method::Invite,
-        }
-        .into(),
-    );
+    headers.push(req.cseq_header().unwrap().clone().into());
     headers.push(Header::Allow(Allow::new(
         "ACK,BYE,CANCEL,INFO,INVITE,NOTIFY,OPTIONS,PRACK,REFER,UPDATE",
     )));
@@ -0,0 +1,17 @@
+use rsip::Header;
+
+use crate::state::dialogs::Dialog;
+
+use super::helper::get_session_headers;
+
+/// Headers of a re-INVITE or an UPDATE within a dialog, offering media
+/// or refreshing the session
+pub fn get_offer_headers(dialog: &Dialog) -> Vec<Header> {
+    let mut headers: Vec<Header> = vec![rsip::headers::Allow::from(
+        "ACK,BYE,CANCEL,INFO,INVITE,NOTIFY,OPTIONS,PRACK,REFER,UPDATE",
+    )
+    .into()];
+    headers.extend(get_session_headers(dialog));
+
+    headers
+}
@@ -1,8 +1,11 @@
 use rsip::{
+    param::Branch,
     prelude::{HeadersExt, ToTypedHeader},
-    Header, Headers, SipMessage,
+    Header, Headers, Param, SipMessage,
 };
 
+use crate::commands::helper::get_branch;
+
 pub trait CustomHeaderExtension {
     fn get_via_header_array(&self) -> Vec<&Header>;
     fn get_record_route_header_array(&self) -> Vec<&Header>;
@@ -44,7 +47,23 @@ pub trait PartialHeaderClone {
 impl PartialHeaderClone for SipMessage {
     fn partial_header_clone(&self, skip_cseq: bool, skip_expires: bool) -> Headers {
         let mut headers: Headers = Default::default();
-        headers.push(self.via_header().unwrap().clone().into());
+
+        // the clone is sent as a new transaction, so it needs a branch of its own
+        let via = self.via_header().unwrap().typed().unwrap();
+        headers.push(
+            rsip::typed::Via {
+                params: via
+                    .params
+                    .into_iter()
+                    .map(|param| match param {
+                        Param::Branch(_) => Param::Branch(Branch::new(get_branch())),
+                        other => other,
+                    })
+                    .collect(),
+                ..via
+            }
+            .into(),
+        );
         headers.push(self.max_forwards_header().unwrap().clone().into());
         headers.push(self.from_header().unwrap().clone().into());
         headers.push(self.to_header().unwrap().clone().into());
@@ -1,7 +1,9 @@
 extern crate serde;
-use serde::Deserialize;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::Read;
+use std::net::IpAddr;
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct JSONConfiguration {
@@ -12,6 +14,200 @@ pub struct JSONConfiguration {
     pub extension: String,
     pub pcap: Option<String>,
     pub reg_timeout: i8,
+    #[serde(default)]
+    pub answer: AnswerPolicy,
+    /// Mailbox to subscribe to for message-waiting indication, usually our own extension
+    pub mwi: Option<String>,
+    /// Extensions to monitor with the dialog event package, busy lamp field
+    #[serde(default)]
+    pub blf: Vec<String>,
+    /// Transport of the signalling with the SIP server
+    #[serde(default)]
+    pub transport: Transport,
+    /// Certificates of the `tls` transport
+    #[serde(default)]
+    pub tls: TlsConfiguration,
+    /// Path of the WebSocket endpoint of the `ws` and `wss` transports, `/` by default
+    pub ws_path: Option<String>,
+    /// Address to bind and advertise in Via, Contact and SDP, picked from the interfaces if missing
+    pub local_ip: Option<IpAddr>,
+    /// Port of our SIP socket
+    #[serde(default = "default_sip_port")]
+    pub local_sip_port: u16,
+    /// Name of the interface the address is picked from, e.g. `eth0`
+    pub interface: Option<String>,
+}
+
+fn default_sip_port() -> u16 {
+    5060
+}
+
+/// SIP transport of an account
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Udp,
+    /// Connections are reused, messages are framed by their Content-Length
+    Tcp,
+    /// TCP wrapped in TLS, addresses are `sips:` uris
+    Tls,
+    /// SIP over WebSocket, one message per frame with the `sip` subprotocol
+    Ws,
+    /// WebSocket over TLS
+    Wss,
+}
+
+impl Transport {
+    /// Scheme of the uris reached over this transport
+    pub fn scheme(&self) -> rsip::Scheme {
+        match self {
+            Transport::Tls => rsip::Scheme::Sips,
+            _ => rsip::Scheme::Sip,
+        }
+    }
+
+    /// Whether the transport delivers on its own, requests and responses
+    /// sent over it are never retransmitted (RFC 3261 17.1.1.2)
+    pub fn is_reliable(&self) -> bool {
+        !matches!(self, Transport::Udp)
+    }
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport::Udp
+    }
+}
+
+impl From<Transport> for rsip::Transport {
+    fn from(transport: Transport) -> Self {
+        match transport {
+            Transport::Udp => rsip::Transport::Udp,
+            Transport::Tcp => rsip::Transport::Tcp,
+            Transport::Tls => rsip::Transport::Tls,
+            Transport::Ws => rsip::Transport::Ws,
+            Transport::Wss => rsip::Transport::Wss,
+        }
+    }
+}
+
+/// PEM files of the `tls` transport
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct TlsConfiguration {
+    /// CA bundle the server certificate is verified against, besides the system store
+    pub ca: Option<String>,
+    /// Client certificate, for servers asking for one
+    pub cert: Option<String>,
+    /// PKCS #8 key of the client certificate
+    pub key: Option<String>,
+    #[serde(default)]
+    pub verify: TlsVerify,
+}
+
+/// How much of the server certificate gets verified
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsVerify {
+    /// Certificate chain and host name
+    Full,
+    /// Certificate chain only, any host name is accepted
+    Ca,
+    /// Any certificate is accepted, for testing against a self-signed server
+    None,
+}
+
+impl Default for TlsVerify {
+    fn default() -> Self {
+        TlsVerify::Full
+    }
+}
+
+/// How inbound calls get answered
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum AnswerPolicy {
+    /// Rings for `ring` seconds with 180 Ringing, then answers
+    Ring {
+        #[serde(default = "default_ring")]
+        ring: u64,
+    },
+    /// Rejects with `code`, e.g. 486, 603 or 480
+    Reject {
+        #[serde(default = "default_reject_code")]
+        code: u16,
+    },
+    /// Keeps ringing until the caller gives up
+    Never,
+    /// Keeps ringing until the call gets answered or rejected over HTTP
+    Manual,
+    /// Answers `percentage` of the calls as `Ring`, rejects the rest with `code`
+    Percentage {
+        percentage: u8,
+        #[serde(default = "default_ring")]
+        ring: u64,
+        #[serde(default = "default_reject_code")]
+        code: u16,
+    },
+}
+
+impl Default for AnswerPolicy {
+    fn default() -> Self {
+        AnswerPolicy::Ring {
+            ring: default_ring(),
+        }
+    }
+}
+
+impl AnswerPolicy {
+    /// Builds a policy from its mode and whichever parameters it uses
+    pub fn from_parts(
+        mode: &str,
+        ring: Option<u64>,
+        code: Option<u16>,
+        percentage: Option<u8>,
+    ) -> Option<Self> {
+        let ring = ring.unwrap_or_else(default_ring);
+        let code = code.unwrap_or_else(default_reject_code);
+
+        match mode {
+            "ring" => Some(AnswerPolicy::Ring { ring }),
+            "reject" => Some(AnswerPolicy::Reject { code }),
+            "never" => Some(AnswerPolicy::Never),
+            "manual" => Some(AnswerPolicy::Manual),
+            "percentage" => Some(AnswerPolicy::Percentage {
+                percentage: percentage?.min(100),
+                ring,
+                code,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Picks what happens to a single call, a `Percentage` becomes either `Ring` or `Reject`
+    pub fn resolve(&self) -> AnswerPolicy {
+        match self {
+            AnswerPolicy::Percentage {
+                percentage,
+                ring,
+                code,
+            } => {
+                if rand::thread_rng().gen_range(0, 100) < *percentage {
+                    AnswerPolicy::Ring { ring: *ring }
+                } else {
+                    AnswerPolicy::Reject { code: *code }
+                }
+            }
+            other => other.clone(),
+        }
+    }
+}
+
+fn default_ring() -> u64 {
+    1
+}
+
+fn default_reject_code() -> u16 {
+    486
 }
 
 pub fn read(filename: &str) -> serde_json::Result<JSONConfiguration> {
@@ -25,3 +221,42 @@ pub fn read(filename: &str) -> serde_json::Result<JSONConfiguration> {
         Err(_why) => panic!("file not found"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{AnswerPolicy, TlsConfiguration, TlsVerify, Transport};
+
+    #[test]
+    fn answer_policy_from_json() {
+        let policy: AnswerPolicy = serde_json::from_str(r#"{"mode":"reject","code":603}"#).unwrap();
+        assert_eq!(policy, AnswerPolicy::Reject { code: 603 });
+
+        let policy: AnswerPolicy = serde_json::from_str(r#"{"mode":"ring"}"#).unwrap();
+        assert_eq!(policy, AnswerPolicy::Ring { ring: 1 });
+    }
+
+    #[test]
+    fn transport_from_json() {
+        let transport: Transport = serde_json::from_str(r#""tcp""#).unwrap();
+        assert_eq!(transport, Transport::Tcp);
+        assert_eq!(rsip::Transport::from(transport), rsip::Transport::Tcp);
+
+        let tls: TlsConfiguration =
+            serde_json::from_str(r#"{"ca":"ca.pem","verify":"none"}"#).unwrap();
+        assert_eq!(tls.ca.as_deref(), Some("ca.pem"));
+        assert_eq!(tls.verify, TlsVerify::None);
+        assert_eq!(Transport::Tls.scheme(), rsip::Scheme::Sips);
+
+        let transport: Transport = serde_json::from_str(r#""wss""#).unwrap();
+        assert_eq!(rsip::Transport::from(transport), rsip::Transport::Wss);
+    }
+
+    #[test]
+    fn percentage_resolves_to_ring_or_reject() {
+        let always = AnswerPolicy::from_parts("percentage", Some(3), None, Some(100)).unwrap();
+        assert_eq!(always.resolve(), AnswerPolicy::Ring { ring: 3 });
+
+        let never = AnswerPolicy::from_parts("percentage", None, Some(480), Some(0)).unwrap();
+        assert_eq!(never.resolve(), AnswerPolicy::Reject { code: 480 });
+    }
+}
@@ -0,0 +1,1267 @@
+use crate::{
+    commands::{
+        helper::{
+            get_answer_direction, get_content_type, get_dial_uri, get_offer_direction,
+            get_other_header, get_refer_to_with_replaces, get_sdp_media, get_sipfrag,
+            get_telephone_event_payload, get_via_host, get_via_port,
+        },
+        notify::get_notify_headers,
+        ok::ok_with_sdp,
+        refer::get_refer_headers,
+        reject::{accepted, reject},
+        update::get_offer_headers,
+    },
+    config::JSONConfiguration,
+    flow::outbound::{outbound_configure, outbound_start},
+    parsers::{
+        get_info_digit, get_refer_to, get_sipfrag_status, parse_rack, session_interval_too_small,
+    },
+    rtp::dtmf::send_dtmf,
+    state::{
+        dialogs::{Dialog, DialogState, Digit, Direction, Referral, State, TransferState},
+        options::{SipOptions, Verbosity},
+        transactions::{Transaction, TransactionState, TransactionType},
+    },
+    transmissions::sockets::{MpscBase, SocketV4},
+};
+use chrono::prelude::*;
+use rsip::{
+    prelude::{HeadersExt, ToTypedHeader},
+    Header, Method, Request, Response, SipMessage, StatusCode,
+};
+use std::{
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+/// Ends a call, an established call gets a BYE within its dialog,
+/// an outbound call that is still ringing gets its INVITE cancelled
+/// and a ringing inbound call gets declined with a 603
+pub fn hang_up(call_id: &str, state: Arc<Mutex<State>>) {
+    let mut transaction: Option<SocketV4> = None;
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+
+        for dg in dialogs.iter_mut() {
+            if dg.call_id != call_id {
+                continue;
+            }
+
+            transaction = match (dg.state, dg.diag_type) {
+                (DialogState::Confirmed, _) => bye(dg),
+                (DialogState::Early, Direction::Outbound) => cancel(dg),
+                (DialogState::Early, Direction::Inbound) => decline(dg, 603.into()),
+                (DialogState::Terminated, _) => None,
+            };
+            if dg.cancel_pending {
+                return;
+            }
+            break;
+        }
+    }
+
+    match transaction {
+        Some(event) => dispatch(&state, Some(event)),
+        None => warn!("no call to hang up with call-id {}", call_id),
+    }
+}
+
+/// Answers a ringing inbound call, the 200 OK goes out with the next `answer_pending`
+pub fn answer(call_id: &str, state: Arc<Mutex<State>>) {
+    let mut locked_state = state.lock().unwrap();
+    let mut dialogs = locked_state.get_dialogs().unwrap();
+
+    match dialogs.iter_mut().find(|dg| is_ringing(dg, call_id)) {
+        Some(dg) => dg.answer_at = Some(Local::now()),
+        None => warn!("no ringing call to answer with call-id {}", call_id),
+    }
+}
+
+/// Rejects a ringing inbound call with a final error response
+pub fn reject_call(call_id: &str, status_code: StatusCode, state: Arc<Mutex<State>>) {
+    let mut transaction: Option<SocketV4> = None;
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+
+        if let Some(dg) = dialogs.iter_mut().find(|dg| is_ringing(dg, call_id)) {
+            transaction = decline(dg, status_code);
+        }
+    }
+
+    match transaction {
+        Some(event) => dispatch(&state, Some(event)),
+        None => warn!("no ringing call to reject with call-id {}", call_id),
+    }
+}
+
+/// Final error response to the INVITE of a ringing inbound call,
+/// its server transaction repeats it until the ACK
+fn decline(dg: &mut Dialog, status_code: StatusCode) -> Option<SocketV4> {
+    let mut transactions = dg.transactions.get_transactions().unwrap();
+    let server = transactions.iter_mut().find(|tr| {
+        matches!(tr.tr_type, TransactionType::InviteServer)
+            && tr.state == TransactionState::Proceeding
+    })?;
+    let invite = match server.remote.clone()? {
+        SipMessage::Request(invite) => invite,
+        SipMessage::Response(_) => return None,
+    };
+
+    let via = invite.via_header().ok()?.typed().ok()?;
+    let rejection = reject(&invite, status_code, Some(&dg.local_tag));
+
+    server.local = Some(rejection.clone());
+    server.start_server(&get_via_host(&via), get_via_port(&via));
+    drop(transactions);
+    dg.answer_at = None;
+    dg.state = DialogState::Terminated;
+
+    Some(SocketV4 {
+        ip: get_via_host(&via),
+        port: get_via_port(&via),
+        bytes: rejection.to_string().as_bytes().to_vec(),
+    })
+}
+
+fn is_ringing(dg: &Dialog, call_id: &str) -> bool {
+    dg.call_id == call_id
+        && matches!(dg.diag_type, Direction::Inbound)
+        && dg.state == DialogState::Early
+}
+
+/// Puts an established call on hold, or resumes it, with a re-INVITE
+pub fn hold(call_id: &str, hold: bool, state: Arc<Mutex<State>>) {
+    let mut transaction: Option<SocketV4> = None;
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+
+        let dg = dialogs
+            .iter_mut()
+            .find(|dg| dg.call_id == call_id && dg.state == DialogState::Confirmed);
+
+        if let Some(dg) = dg {
+            let direction = get_offer_direction(hold, dg.remote_hold);
+            transaction = reinvite(dg, direction);
+        }
+    }
+
+    match transaction {
+        Some(event) => dispatch(&state, Some(event)),
+        None => warn!("no established call to hold with call-id {}", call_id),
+    }
+}
+
+/// Offers the current media of a call, early or established, again with an UPDATE
+pub fn update(call_id: &str, state: Arc<Mutex<State>>) {
+    let mut transaction: Option<SocketV4> = None;
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+
+        let dg = dialogs.iter_mut().find(|dg| {
+            dg.call_id == call_id
+                && dg.remote_tag.is_some()
+                && matches!(dg.state, DialogState::Early | DialogState::Confirmed)
+        });
+
+        if let Some(dg) = dg {
+            transaction = send_update(dg, true);
+        }
+    }
+
+    match transaction {
+        Some(event) => dispatch(&state, Some(event)),
+        None => warn!("no call to update with call-id {}", call_id),
+    }
+}
+
+/// Takes the offer of an UPDATE (RFC 3311), early or within an established call,
+/// returns the SDP answer, or none for a session refresh without an offer
+pub fn update_received(
+    request: &Request,
+    state: &mut State,
+    ip: &str,
+) -> Result<Option<String>, StatusCode> {
+    let mut dialogs = state.get_dialogs().unwrap();
+    let dg = match dialogs
+        .iter_mut()
+        .find(|dg| dg.matches_request(request) && dg.state != DialogState::Terminated)
+    {
+        Some(dg) => dg,
+        None => return Err(StatusCode::CallTransactionDoesNotExist),
+    };
+
+    if session_interval_too_small(&request.headers) {
+        info!(
+            "UPDATE of {} asks for too short a session interval",
+            dg.call_id
+        );
+        return Err(StatusCode::from(422));
+    }
+
+    if request.body.is_empty() {
+        info!("session of {} refreshed with an UPDATE", dg.call_id);
+        dg.refreshed_by(request);
+        return Ok(None);
+    }
+
+    // an offer of ours is still waiting for its answer
+    let confirmed = dg.state == DialogState::Confirmed;
+    let pending = dg
+        .transactions
+        .get_transactions()
+        .unwrap()
+        .iter()
+        .any(|tr| {
+            let offer = tr.method() == Some(Method::Update)
+                || (confirmed && matches!(tr.tr_type, TransactionType::Invite));
+            offer
+                && matches!(
+                    tr.state,
+                    TransactionState::Calling
+                        | TransactionState::Trying
+                        | TransactionState::Proceeding
+                )
+        });
+    if pending {
+        warn!("UPDATE of {} collides with our own offer", dg.call_id);
+        return Err(StatusCode::RequestPending);
+    }
+
+    dg.remote_media = get_sdp_media(&request.body);
+    dg.dtmf_payload = get_telephone_event_payload(&request.body);
+    dg.offer_received(&request.body);
+    dg.refreshed_by(request);
+    let direction = get_answer_direction(&request.body, dg.on_hold);
+    info!("media of {} updated, answering {}", dg.call_id, direction);
+
+    Ok(Some(dg.get_local_sdp(ip, direction)))
+}
+
+/// Answers a re-INVITE within an established call, a media change or a session
+/// refresh, its server transaction repeats the 200 until the ACK
+pub fn reinvite_received(
+    conf: &JSONConfiguration,
+    request: &Request,
+    state: &mut State,
+    ip: &str,
+) -> Option<SipMessage> {
+    let via = request.via_header().ok()?.typed().ok()?;
+    let mut dialogs = state.get_dialogs().unwrap();
+    let dg = dialogs
+        .iter_mut()
+        .find(|dg| dg.matches_request(request) && dg.state == DialogState::Confirmed)?;
+
+    // too short a session interval gets a 422 and leaves the call as it was
+    let answer = if session_interval_too_small(&request.headers) {
+        info!(
+            "re-INVITE on {} asks for too short a session interval",
+            dg.call_id
+        );
+        reject(request, StatusCode::from(422), Some(&dg.local_tag))
+    } else {
+        // a re-INVITE without an offer gets ours
+        if !request.body.is_empty() {
+            dg.remote_media = get_sdp_media(&request.body);
+            dg.dtmf_payload = get_telephone_event_payload(&request.body);
+            dg.offer_received(&request.body);
+        }
+        // a call we hold stays held whatever the other side offers
+        let direction = get_answer_direction(&request.body, dg.on_hold);
+        let sdp = dg.get_local_sdp(ip, direction);
+        let answer = ok_with_sdp(conf, ip, request, Method::Invite, &sdp);
+        dg.refreshed_by(request);
+        info!("re-INVITE on {} answered", dg.call_id);
+        answer
+    };
+
+    let target = get_target(dg);
+    let mut transactions = dg.transactions.get_transactions().unwrap();
+    let position = match transactions
+        .iter()
+        .position(|tr| tr.matches_request(request))
+    {
+        Some(position) => position,
+        None => {
+            transactions.push(Transaction::new(
+                TransactionType::InviteServer,
+                target?.0,
+                None,
+            ));
+            transactions.len() - 1
+        }
+    };
+    let server = &mut transactions[position];
+    server.remote = Some(request.clone().into());
+    server.local = Some(answer.clone());
+    server.start_server(&get_via_host(&via), get_via_port(&via));
+
+    Some(answer)
+}
+
+/// Refreshes the sessions we are the refresher of and hangs up the calls
+/// whose session ran out without a refresh (RFC 4028)
+pub fn refresh_sessions(state: &Arc<Mutex<State>>) {
+    let now = Local::now();
+    let mut transactions: Vec<SocketV4> = vec![];
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+
+        for dg in dialogs.iter_mut() {
+            if dg.state != DialogState::Confirmed || dg.session_expires.is_none() {
+                continue;
+            }
+
+            if dg
+                .session_expires_at
+                .map_or(false, |expires_at| expires_at <= now)
+            {
+                warn!(
+                    "session of {} expired without a refresh, hanging up",
+                    dg.call_id
+                );
+                dg.stop_session_timer();
+                transactions.extend(bye(dg));
+                continue;
+            }
+
+            if dg.refresh_at.map_or(false, |refresh_at| refresh_at <= now) {
+                info!("refreshing session of {}", dg.call_id);
+                dg.refresh_at = None;
+                let direction = get_offer_direction(dg.on_hold, dg.remote_hold);
+                transactions.extend(match dg.update_allowed {
+                    true => send_update(dg, false),
+                    false => reinvite(dg, direction),
+                });
+            }
+        }
+    }
+
+    dispatch(state, transactions);
+}
+
+/// Sends `digits` as telephone-events on the media of an established call
+pub fn dtmf(call_id: &str, digits: &str, state: Arc<Mutex<State>>) {
+    let mut media: Option<(u8, (String, u16))> = None;
+    {
+        let mut locked_state = state.lock().unwrap();
+        let dialogs = locked_state.get_dialogs().unwrap();
+
+        let dg = dialogs
+            .iter()
+            .find(|dg| dg.call_id == call_id && dg.state == DialogState::Confirmed);
+
+        if let Some(dg) = dg {
+            match (dg.dtmf_payload, &dg.remote_media) {
+                (Some(payload), Some(address)) => media = Some((payload, address.clone())),
+                (None, _) => warn!("telephone-event was not negotiated for {}", call_id),
+                (_, None) => warn!("no media address known for {}", call_id),
+            }
+        } else {
+            warn!("no established call to send dtmf with call-id {}", call_id);
+        }
+    }
+
+    if let Some((payload, address)) = media {
+        send_dtmf(digits, payload, address);
+    }
+}
+
+/// Blind transfer, asks the other side of an established call to call `target` instead
+pub fn transfer(call_id: &str, target: &str, conf: &JSONConfiguration, state: Arc<Mutex<State>>) {
+    let refer_to = match target.starts_with("sip:") || target.starts_with("sips:") {
+        true => target.to_string(),
+        false => get_dial_uri(
+            target,
+            &conf.sip_server,
+            &conf.sip_port.to_string(),
+            conf.transport,
+        ),
+    };
+
+    let mut transaction: Option<SocketV4> = None;
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+
+        let dg = dialogs
+            .iter_mut()
+            .find(|dg| dg.call_id == call_id && dg.state == DialogState::Confirmed);
+
+        if let Some(dg) = dg {
+            transaction = refer(dg, &refer_to);
+        }
+    }
+
+    match transaction {
+        Some(event) => {
+            info!("transferring {} to {}", call_id, refer_to);
+            dispatch(&state, Some(event));
+        }
+        None => warn!("no established call to transfer with call-id {}", call_id),
+    }
+}
+
+/// Attended transfer, puts the call on hold and dials `target`, the held call
+/// gets transferred with a Replaces for the new call once `target` answers
+pub fn attended_transfer(
+    call_id: &str,
+    target: &str,
+    conf: &JSONConfiguration,
+    ip: &IpAddr,
+    state: Arc<Mutex<State>>,
+    vrb: &Verbosity,
+) {
+    let established = {
+        let mut locked_state = state.lock().unwrap();
+        let dialogs = locked_state.get_dialogs().unwrap();
+        dialogs
+            .iter()
+            .any(|dg| dg.call_id == call_id && dg.state == DialogState::Confirmed)
+    };
+    if !established {
+        warn!("no established call to transfer with call-id {}", call_id);
+        return;
+    }
+
+    hold(call_id, true, state.clone());
+
+    let consultation = outbound_configure(conf, ip, target, None, state.clone());
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+        if let Some(dg) = dialogs.iter_mut().find(|dg| dg.call_id == call_id) {
+            dg.consultation = Some(consultation.clone());
+        }
+    }
+    info!("consulting {} with call-id {}", target, consultation);
+
+    outbound_start(conf, &consultation, state, vrb);
+}
+
+/// Sends the REFER of every attended transfer whose consultation call got answered,
+/// a consultation call that failed resumes the held call instead
+pub fn complete_transfers(state: &Arc<Mutex<State>>) {
+    let mut transactions: Vec<SocketV4> = vec![];
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+
+        let pending: Vec<(String, String)> = dialogs
+            .iter()
+            .filter_map(|dg| {
+                dg.consultation
+                    .clone()
+                    .map(|consultation| (dg.call_id.clone(), consultation))
+            })
+            .collect();
+
+        for (call_id, consultation) in pending {
+            // the target side tags, to-tag is theirs and from-tag ours
+            let (consultation_state, refer_to) =
+                match dialogs.iter().find(|dg| dg.call_id == consultation) {
+                    Some(dg) => (
+                        dg.state,
+                        get_refer_to_with_replaces(
+                            dg.remote_target
+                                .as_ref()
+                                .or(dg.remote_uri.as_ref())
+                                .map(|uri| uri.as_str())
+                                .unwrap_or_default(),
+                            &dg.call_id,
+                            dg.remote_tag.as_deref().unwrap_or_default(),
+                            &dg.local_tag,
+                        ),
+                    ),
+                    None => (DialogState::Terminated, String::new()),
+                };
+
+            let dg = match dialogs.iter_mut().find(|dg| dg.call_id == call_id) {
+                Some(dg) => dg,
+                None => continue,
+            };
+
+            let event = match consultation_state {
+                DialogState::Early => continue,
+                DialogState::Confirmed => {
+                    info!("transferring {} to {}", call_id, consultation);
+                    refer(dg, &refer_to)
+                }
+                DialogState::Terminated => {
+                    warn!(
+                        "consultation call {} failed, resuming {}",
+                        consultation, call_id
+                    );
+                    let direction = get_offer_direction(false, dg.remote_hold);
+                    reinvite(dg, direction)
+                }
+            };
+            dg.consultation = None;
+            transactions.extend(event);
+        }
+    }
+
+    dispatch(state, transactions);
+}
+
+/// Follows the message/sipfrag NOTIFYs of a transfer we asked for,
+/// returns the call-id of the call to hang up once the transfer is over
+pub fn transfer_progress(request: &Request, state: &mut State) -> Option<String> {
+    if get_content_type(&request.headers).as_deref() != Some("message/sipfrag") {
+        return None;
+    }
+    let code = get_sipfrag_status(&request.body)?;
+
+    let mut dialogs = state.get_dialogs().unwrap();
+    let dg = dialogs
+        .iter_mut()
+        .find(|dg| dg.matches_request(request) && dg.transfer.is_some())?;
+
+    dg.transfer = Some(match code {
+        100..=199 => TransferState::Trying(code),
+        200..=299 => TransferState::Succeeded(code),
+        _ => TransferState::Failed(code),
+    });
+
+    match dg.transfer {
+        Some(TransferState::Succeeded(_)) => {
+            info!("transfer of {} succeeded with {}", dg.call_id, code);
+            Some(dg.call_id.clone())
+        }
+        Some(TransferState::Failed(_)) => {
+            warn!("transfer of {} failed with {}", dg.call_id, code);
+            Some(dg.call_id.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Takes what the response to a request of ours within a call means to the call
+pub fn call_answered(dg: &mut Dialog, method: Method, response: &Response) {
+    let code = response.status_code.code();
+
+    match (method, code) {
+        // a rejected REFER never gets a NOTIFY
+        (Method::Refer, 200..=299) => dg.transfer = Some(TransferState::Accepted),
+        (Method::Refer, 300..=699) => {
+            warn!("transfer of {} refused with {}", dg.call_id, code);
+            dg.transfer = Some(TransferState::Failed(code));
+        }
+        // the answer to the offer of our UPDATE
+        (Method::Update, 200..=299) if !response.body.is_empty() => {
+            dg.remote_media = get_sdp_media(&response.body);
+            dg.dtmf_payload = get_telephone_event_payload(&response.body);
+        }
+        (Method::Update, 300..=699) => warn!("UPDATE of {} refused with {}", dg.call_id, code),
+        _ => {}
+    }
+
+    // a 2xx to an INVITE or UPDATE of ours refreshes the session
+    if matches!(method, Method::Invite | Method::Update) && (200..=299).contains(&code) {
+        dg.on_refresh_response(response);
+    }
+}
+
+/// Records the digit an INFO carries, an INFO outside of any call gets a 481
+pub fn info_received(request: &Request, state: &mut State) -> StatusCode {
+    let mut dialogs = state.get_dialogs().unwrap();
+    let dg = match dialogs.iter_mut().find(|dg| dg.matches_request(request)) {
+        Some(dg) => dg,
+        None => return StatusCode::CallTransactionDoesNotExist,
+    };
+
+    let digit = get_content_type(&request.headers)
+        .and_then(|content_type| get_info_digit(&content_type, &request.body));
+
+    if let Some((digit, duration)) = digit {
+        info!("dtmf {} received on {}", digit, dg.call_id);
+        dg.digits.push(Digit { digit, duration });
+    }
+
+    StatusCode::OK
+}
+
+/// Acknowledges our reliable provisional response (RFC 3262), a PRACK
+/// whose RAck does not match the last RSeq sent gets a 481
+pub fn prack_received(request: &Request, state: &mut State) -> StatusCode {
+    let rack = match get_other_header(&request.headers, "RAck").and_then(|rack| parse_rack(&rack)) {
+        Some(rack) => rack,
+        None => return StatusCode::BadRequest,
+    };
+
+    let mut dialogs = state.get_dialogs().unwrap();
+    let dg = match dialogs
+        .iter_mut()
+        .find(|dg| dg.matches_request(request) && dg.rseq == Some(rack.0))
+    {
+        Some(dg) => dg,
+        None => return StatusCode::CallTransactionDoesNotExist,
+    };
+
+    let mut transactions = dg.transactions.get_transactions().unwrap();
+    for transaction in transactions
+        .iter_mut()
+        .filter(|tr| matches!(tr.tr_type, TransactionType::InviteServer))
+    {
+        transaction.on_prack();
+    }
+
+    info!(
+        "provisional response {} acknowledged on {}",
+        rack.0, dg.call_id
+    );
+    StatusCode::OK
+}
+
+/// Accepts a REFER with 202 and calls the `Refer-To` uri, along with the Replaces it carries,
+/// the referrer gets NOTIFYed about the new call by `notify_referrers`
+pub fn referred(
+    request: &Request,
+    conf: &JSONConfiguration,
+    ip: &IpAddr,
+    state: &Arc<Mutex<State>>,
+    vrb: &Verbosity,
+) {
+    let via = request.via_header().unwrap().typed().unwrap();
+    let target =
+        get_other_header(&request.headers, "Refer-To").and_then(|refer_to| get_refer_to(&refer_to));
+
+    let established = {
+        let mut locked_state = state.lock().unwrap();
+        let dialogs = locked_state.get_dialogs().unwrap();
+        dialogs
+            .iter()
+            .any(|dg| dg.matches_request(request) && dg.state == DialogState::Confirmed)
+    };
+
+    let response = match (&target, established) {
+        (_, false) => reject(request, StatusCode::CallTransactionDoesNotExist, None),
+        (None, true) => reject(request, StatusCode::BadRequest, None),
+        (Some(_), true) => accepted(request),
+    };
+
+    dispatch(
+        state,
+        Some(SocketV4 {
+            ip: get_via_host(&via),
+            port: get_via_port(&via),
+            bytes: response.to_string().as_bytes().to_vec(),
+        }),
+    );
+
+    let target = match (target, established) {
+        (Some(target), true) => target,
+        _ => return,
+    };
+
+    let referral = outbound_configure(conf, ip, &target, None, state.clone());
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+        if let Some(dg) = dialogs.iter_mut().find(|dg| dg.matches_request(request)) {
+            info!("{} referred us to {}", dg.call_id, target);
+            dg.referral = Some(Referral {
+                call_id: referral.clone(),
+                reported: None,
+            });
+        }
+    }
+
+    outbound_start(conf, &referral, state.clone(), vrb);
+}
+
+/// NOTIFYs every referrer whose referred call changed state since the last NOTIFY,
+/// the subscription terminates with the final status of the call
+pub fn notify_referrers(state: &Arc<Mutex<State>>) {
+    let mut transactions: Vec<SocketV4> = vec![];
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+
+        let pending: Vec<(String, String)> = dialogs
+            .iter()
+            .filter_map(|dg| {
+                dg.referral
+                    .as_ref()
+                    .map(|referral| (dg.call_id.clone(), referral.call_id.clone()))
+            })
+            .collect();
+
+        for (call_id, referral) in pending {
+            let code = match dialogs.iter_mut().find(|dg| dg.call_id == referral) {
+                Some(dg) => get_call_status(dg),
+                None => 500,
+            };
+
+            let dg = match dialogs.iter_mut().find(|dg| dg.call_id == call_id) {
+                Some(dg) => dg,
+                None => continue,
+            };
+            if dg.referral.as_ref().and_then(|referral| referral.reported) == Some(code) {
+                continue;
+            }
+
+            let subscription_state = if code >= 200 {
+                dg.referral = None;
+                "terminated;reason=noresource"
+            } else {
+                if let Some(referral) = dg.referral.as_mut() {
+                    referral.reported = Some(code);
+                }
+                "active;expires=60"
+            };
+
+            transactions.extend(notify(
+                dg,
+                "refer",
+                subscription_state,
+                Some(("message/sipfrag", get_sipfrag(code).as_bytes().to_vec())),
+            ));
+        }
+    }
+
+    dispatch(state, transactions);
+}
+
+/// Status of a call we originated, as a status code of its INVITE
+fn get_call_status(dg: &mut Dialog) -> u16 {
+    let result = dg
+        .transactions
+        .get_transactions()
+        .unwrap()
+        .iter()
+        .rev()
+        .find(|tr| matches!(tr.tr_type, TransactionType::Invite))
+        .and_then(|tr| tr.result.clone())
+        .map(|code| code.code());
+
+    match dg.state {
+        DialogState::Early => 100,
+        DialogState::Confirmed => 200,
+        DialogState::Terminated => result.filter(|code| *code >= 300).unwrap_or(487),
+    }
+}
+
+/// ACKs the final response to a re-INVITE sent within an inbound call,
+/// the ACK of a 2xx is a new transaction, the ACK of a failure reuses the INVITE Via
+pub fn acknowledge_reinvite(response: &Response, state: &Arc<Mutex<State>>) {
+    let mut transaction: Option<SocketV4> = None;
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+
+        for dg in dialogs.iter_mut() {
+            let reinvite = dg
+                .transactions
+                .get_transactions()
+                .unwrap()
+                .iter()
+                .find(|tr| {
+                    matches!(tr.tr_type, TransactionType::Invite) && tr.matches_response(response)
+                })
+                .cloned();
+
+            let reinvite = match reinvite {
+                Some(reinvite) => reinvite,
+                None => continue,
+            };
+            let invite = match &reinvite.local {
+                Some(SipMessage::Request(invite)) => invite.clone(),
+                _ => break,
+            };
+            let destination = match reinvite.destination.clone() {
+                Some(destination) => destination,
+                None => break,
+            };
+
+            let cseq = invite.cseq_header().unwrap().typed().unwrap().seq;
+            let mut ack =
+                reinvite
+                    .object
+                    .create_in_dialog_request(dg, Method::Ack, cseq, vec![], None);
+
+            if (200..=299).contains(&response.status_code.code()) {
+                dg.offer_answered(&invite.body);
+            }
+            if response.status_code.code() >= 300 {
+                if let SipMessage::Request(ack) = &mut ack {
+                    for header in ack.headers.iter_mut() {
+                        if let Header::Via(_) = header {
+                            *header = invite.via_header().unwrap().clone().into();
+                        }
+                    }
+                }
+            }
+
+            transaction = Some(SocketV4 {
+                ip: destination.0,
+                port: destination.1,
+                bytes: ack.to_string().as_bytes().to_vec(),
+            });
+            dg.transactions
+                .get_transactions()
+                .unwrap()
+                .push(Transaction::new(
+                    TransactionType::Ack,
+                    reinvite.object.clone(),
+                    Some(ack),
+                ));
+            break;
+        }
+    }
+
+    dispatch(state, transaction);
+}
+
+/// Options and address of the requests sent within the dialog,
+/// taken from the transaction that set the call up
+pub fn get_target(dg: &mut Dialog) -> Option<(SipOptions, (String, u16))> {
+    let transactions = dg.transactions.get_transactions().unwrap();
+    let tr = transactions.iter().find(|tr| tr.destination.is_some())?;
+
+    Some((tr.object.clone(), tr.destination.clone()?))
+}
+
+/// re-INVITE offering media in `direction`, the call is held or resumed once it gets answered
+fn reinvite(dg: &mut Dialog, direction: &str) -> Option<SocketV4> {
+    let ip = get_target(dg)?.0.ip;
+    let sdp = dg.get_local_sdp(&ip, direction);
+    let headers = get_offer_headers(dg);
+
+    send_in_dialog(
+        dg,
+        Method::Invite,
+        headers,
+        Some(("application/sdp", sdp.into_bytes())),
+    )
+}
+
+/// UPDATE offering the current media direction, or refreshing the session without an offer
+fn send_update(dg: &mut Dialog, offer: bool) -> Option<SocketV4> {
+    let direction = get_offer_direction(dg.on_hold, dg.remote_hold);
+    let ip = get_target(dg)?.0.ip;
+    let sdp = match offer {
+        true => Some(dg.get_local_sdp(&ip, direction)),
+        false => None,
+    };
+    let headers = get_offer_headers(dg);
+
+    send_in_dialog(
+        dg,
+        Method::Update,
+        headers,
+        sdp.map(|sdp| ("application/sdp", sdp.into_bytes())),
+    )
+}
+
+/// REFER asking the other side to call `refer_to`
+fn refer(dg: &mut Dialog, refer_to: &str) -> Option<SocketV4> {
+    let headers = get_refer_headers(dg, refer_to);
+    let event = send_in_dialog(dg, Method::Refer, headers, None)?;
+    dg.transfer = Some(TransferState::Requested);
+
+    Some(event)
+}
+
+/// NOTIFY for the `event` package within the dialog
+fn notify(
+    dg: &mut Dialog,
+    event: &str,
+    subscription_state: &str,
+    body: Option<(&str, Vec<u8>)>,
+) -> Option<SocketV4> {
+    let headers = get_notify_headers(event, subscription_state);
+
+    send_in_dialog(dg, Method::Notify, headers, body)
+}
+
+/// BYE within the dialog, the dialog terminates once it gets answered
+pub fn bye(dg: &mut Dialog) -> Option<SocketV4> {
+    send_in_dialog(dg, Method::Bye, vec![], None)
+}
+
+/// Request within the dialog on a client transaction of its own,
+/// `body` is the content type along with the content
+fn send_in_dialog(
+    dg: &mut Dialog,
+    method: Method,
+    headers: Vec<Header>,
+    body: Option<(&str, Vec<u8>)>,
+) -> Option<SocketV4> {
+    let (object, destination) = get_target(dg)?;
+
+    let tr_type = match method {
+        Method::Invite => TransactionType::Invite,
+        _ => TransactionType::Typical,
+    };
+    let cseq = dg.next_cseq();
+    let request = object.create_in_dialog_request(dg, method, cseq, headers, body);
+    let mut transaction = Transaction::new(tr_type, object, Some(request));
+    transaction.start(&destination.0, destination.1);
+
+    let event = SocketV4 {
+        ip: destination.0,
+        port: destination.1,
+        bytes: transaction.local.as_ref()?.to_string().as_bytes().to_vec(),
+    };
+    dg.transactions
+        .get_transactions()
+        .unwrap()
+        .push(transaction);
+
+    Some(event)
+}
+
+/// Hands requests and responses over to the SIP event loop
+fn dispatch(state: &Arc<Mutex<State>>, events: impl IntoIterator<Item = SocketV4>) {
+    let mut events = events.into_iter().peekable();
+    if events.peek().is_none() {
+        return;
+    }
+
+    let mut locked_state = state.lock().unwrap();
+    let channel = locked_state.get_sip_channel().unwrap();
+    for event in events {
+        channel
+            .0
+            .send(MpscBase {
+                event: Some(event),
+                exit: false,
+            })
+            .unwrap();
+    }
+}
+
+/// Sends the CANCEL of every call hung up before its INVITE got a provisional response,
+/// once one arrived, a call answered in the meantime gets a BYE instead
+pub fn cancel_hung_up(state: &Arc<Mutex<State>>) {
+    let mut transactions: Vec<SocketV4> = vec![];
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+
+        for dg in dialogs.iter_mut().filter(|dg| dg.cancel_pending) {
+            let event = match dg.state {
+                DialogState::Early => cancel(dg),
+                DialogState::Confirmed => {
+                    dg.cancel_pending = false;
+                    bye(dg)
+                }
+                DialogState::Terminated => {
+                    dg.cancel_pending = false;
+                    None
+                }
+            };
+            transactions.extend(event);
+        }
+    }
+
+    dispatch(state, transactions);
+}
+
+/// CANCEL of the pending INVITE, the 487 that follows gets ACKed by the outbound flow
+fn cancel(dg: &mut Dialog) -> Option<SocketV4> {
+    let mut transactions = dg.transactions.get_transactions().unwrap();
+    let invite = transactions.iter().rev().find(|tr| {
+        matches!(tr.tr_type, TransactionType::Invite)
+            && matches!(
+                tr.state,
+                TransactionState::Calling | TransactionState::Proceeding
+            )
+    })?;
+
+    // the CANCEL goes out with the first provisional response
+    if invite.state == TransactionState::Calling {
+        info!(
+            "{} gets cancelled once its INVITE gets a provisional response",
+            dg.call_id
+        );
+        dg.cancel_pending = true;
+        return None;
+    }
+    dg.cancel_pending = false;
+
+    let (object, destination) = (invite.object.clone(), invite.destination.clone()?);
+    let request = match invite.local.as_ref()? {
+        SipMessage::Request(request) => request.clone(),
+        SipMessage::Response(_) => return None,
+    };
+
+    let mut cancel = Transaction::new(
+        TransactionType::Typical,
+        object.clone(),
+        Some(object.create_cancel(&request)),
+    );
+    cancel.start(&destination.0, destination.1);
+
+    let event = SocketV4 {
+        ip: destination.0,
+        port: destination.1,
+        bytes: cancel.local.as_ref()?.to_string().as_bytes().to_vec(),
+    };
+    transactions.push(cancel);
+
+    Some(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        commands::helper::{get_sdp_direction, get_sdp_with_direction},
+        flow::call::{cancel_hung_up, hang_up, hold, update_received},
+        state::{
+            dialogs::{Dialog, DialogState, Direction, State},
+            transactions::{tests::options, Transaction, TransactionType},
+        },
+    };
+    use rsip::{
+        prelude::{HeadersExt, ToTypedHeader},
+        Method, SipMessage, StatusCode,
+    };
+    use std::{
+        convert::TryFrom,
+        sync::{mpsc::channel, Arc, Mutex},
+    };
+
+    fn invite() -> SipMessage {
+        SipMessage::try_from(
+            "INVITE sip:1001@10.0.0.1 SIP/2.0\r\nVia: SIP/2.0/UDP 10.0.0.2:5070;branch=z9hG4bK1\r\nFrom: <sip:1002@10.0.0.2>;tag=theirs\r\nTo: <sip:1001@10.0.0.1>\r\nCall-ID: ringing\r\nCSeq: 1 INVITE\r\nContent-Length: 0\r\n\r\n"
+                .to_string(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn hang_up_declines_a_ringing_inbound_call() {
+        let state = Arc::new(Mutex::new(State::new(channel(), channel())));
+        let mut dg = Dialog::new(
+            Direction::Inbound,
+            "ringing".to_string(),
+            "ours".to_string(),
+        );
+        let mut server = Transaction::new(TransactionType::InviteServer, options(), None);
+        server.remote = Some(invite());
+        dg.transactions.get_transactions().unwrap().push(server);
+        state.lock().unwrap().get_dialogs().unwrap().push(dg);
+
+        hang_up("ringing", state.clone());
+
+        let mut locked_state = state.lock().unwrap();
+        let sent = locked_state
+            .get_sip_channel()
+            .unwrap()
+            .1
+            .try_recv()
+            .unwrap()
+            .event
+            .unwrap();
+        assert_eq!(sent.ip, "10.0.0.2");
+        assert_eq!(sent.port, 5070);
+        match SipMessage::try_from(sent.bytes).unwrap() {
+            SipMessage::Response(response) => {
+                assert_eq!(response.status_code, StatusCode::from(603))
+            }
+            SipMessage::Request(_) => panic!("expected a final response"),
+        }
+        assert_eq!(
+            locked_state.get_dialogs().unwrap()[0].state,
+            DialogState::Terminated
+        );
+    }
+
+    #[test]
+    fn hang_up_cancels_a_ringing_outbound_call() {
+        let state = Arc::new(Mutex::new(State::new(channel(), channel())));
+        let mut dg = Dialog::new(
+            Direction::Outbound,
+            "calling".to_string(),
+            "ours".to_string(),
+        );
+        let mut invite = Transaction::new(
+            TransactionType::Invite,
+            options(),
+            Some(
+                SipMessage::try_from(
+                    "INVITE sip:1002@sip.server.com SIP/2.0\r\nVia: SIP/2.0/UDP 10.0.0.1:5060;branch=z9hG4bKout\r\nFrom: <sip:1004@sip.server.com>;tag=ours\r\nTo: <sip:1002@sip.server.com>\r\nCall-ID: calling\r\nCSeq: 7 INVITE\r\nContent-Length: 0\r\n\r\n"
+                        .to_string(),
+                )
+                .unwrap(),
+            ),
+        );
+        invite.start("sip.server.com", 5060);
+        invite.on_response(&StatusCode::Ringing);
+        dg.transactions.get_transactions().unwrap().push(invite);
+        state.lock().unwrap().get_dialogs().unwrap().push(dg);
+
+        hang_up("calling", state.clone());
+
+        let mut locked_state = state.lock().unwrap();
+        let sent = locked_state
+            .get_sip_channel()
+            .unwrap()
+            .1
+            .try_recv()
+            .unwrap()
+            .event
+            .unwrap();
+        assert_eq!((sent.ip.as_str(), sent.port), ("sip.server.com", 5060));
+        match SipMessage::try_from(sent.bytes).unwrap() {
+            SipMessage::Request(cancel) => {
+                assert_eq!(cancel.method, Method::Cancel);
+                let via = cancel.via_header().unwrap().typed().unwrap();
+                assert_eq!(via.branch().unwrap().to_string(), "z9hG4bKout");
+                let cseq = cancel.cseq_header().unwrap().typed().unwrap();
+                assert_eq!((cseq.seq, cseq.method), (7, Method::Cancel));
+            }
+            SipMessage::Response(_) => panic!("expected a CANCEL"),
+        }
+
+        let dialogs = locked_state.get_dialogs().unwrap();
+        assert_eq!(dialogs[0].state, DialogState::Early);
+        assert_eq!(dialogs[0].transactions.get_transactions().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn hang_up_cancels_once_the_call_rings() {
+        let state = Arc::new(Mutex::new(State::new(channel(), channel())));
+        let mut dg = Dialog::new(
+            Direction::Outbound,
+            "calling".to_string(),
+            "ours".to_string(),
+        );
+        let mut invite = Transaction::new(
+            TransactionType::Invite,
+            options(),
+            Some(
+                SipMessage::try_from(
+                    "INVITE sip:1002@sip.server.com SIP/2.0\r\nVia: SIP/2.0/UDP 10.0.0.1:5060;branch=z9hG4bKout\r\nFrom: <sip:1004@sip.server.com>;tag=ours\r\nTo: <sip:1002@sip.server.com>\r\nCall-ID: calling\r\nCSeq: 7 INVITE\r\nContent-Length: 0\r\n\r\n"
+                        .to_string(),
+                )
+                .unwrap(),
+            ),
+        );
+        invite.start("sip.server.com", 5060);
+        dg.transactions.get_transactions().unwrap().push(invite);
+        state.lock().unwrap().get_dialogs().unwrap().push(dg);
+
+        // nothing came back yet, the CANCEL has to wait
+        hang_up("calling", state.clone());
+        cancel_hung_up(&state);
+        {
+            let mut locked_state = state.lock().unwrap();
+            assert!(locked_state
+                .get_sip_channel()
+                .unwrap()
+                .1
+                .try_recv()
+                .is_err());
+            let mut dialogs = locked_state.get_dialogs().unwrap();
+            assert!(dialogs[0].cancel_pending);
+            dialogs[0].transactions.get_transactions().unwrap()[0].on_response(&StatusCode::Trying);
+        }
+
+        cancel_hung_up(&state);
+
+        let mut locked_state = state.lock().unwrap();
+        let sent = locked_state
+            .get_sip_channel()
+            .unwrap()
+            .1
+            .try_recv()
+            .unwrap()
+            .event
+            .unwrap();
+        match SipMessage::try_from(sent.bytes).unwrap() {
+            SipMessage::Request(cancel) => assert_eq!(cancel.method, Method::Cancel),
+            SipMessage::Response(_) => panic!("expected a CANCEL"),
+        }
+        assert!(!locked_state.get_dialogs().unwrap()[0].cancel_pending);
+    }
+
+    #[test]
+    fn hold_waits_for_the_answer() {
+        let state = Arc::new(Mutex::new(State::new(channel(), channel())));
+        let mut dg = Dialog::new(Direction::Outbound, "held".to_string(), "ours".to_string());
+        dg.state = DialogState::Confirmed;
+        dg.remote_tag = Some("theirs".to_string());
+        dg.local_uri = Some("sip:1004@sip.server.com".to_string());
+        dg.remote_uri = Some("sip:1002@sip.server.com".to_string());
+        let mut invite = Transaction::new(TransactionType::Invite, options(), None);
+        invite.start("sip.server.com", 5060);
+        dg.transactions.get_transactions().unwrap().push(invite);
+        state.lock().unwrap().get_dialogs().unwrap().push(dg);
+
+        let offer = |state: &Arc<Mutex<State>>| {
+            let mut locked_state = state.lock().unwrap();
+            let sent = locked_state
+                .get_sip_channel()
+                .unwrap()
+                .1
+                .try_recv()
+                .unwrap()
+                .event
+                .unwrap();
+            match SipMessage::try_from(sent.bytes).unwrap() {
+                SipMessage::Request(reinvite) => {
+                    assert_eq!(reinvite.method, Method::Invite);
+                    reinvite.body
+                }
+                SipMessage::Response(_) => panic!("expected a re-INVITE"),
+            }
+        };
+
+        hold("held", true, state.clone());
+        let sendonly = offer(&state);
+        assert!(String::from_utf8_lossy(&sendonly).contains("a=sendonly"));
+        {
+            let mut locked_state = state.lock().unwrap();
+            let mut dialogs = locked_state.get_dialogs().unwrap();
+            assert!(!dialogs[0].on_hold);
+            dialogs[0].offer_answered(&sendonly);
+            assert!(dialogs[0].on_hold);
+            dialogs[0].remote_hold = true;
+        }
+
+        hold("held", true, state.clone());
+        assert!(String::from_utf8_lossy(&offer(&state)).contains("a=inactive"));
+
+        hold("held", false, state.clone());
+        let recvonly = offer(&state);
+        assert!(String::from_utf8_lossy(&recvonly).contains("a=recvonly"));
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+        dialogs[0].offer_answered(&recvonly);
+        assert!(!dialogs[0].on_hold);
+    }
+
+    #[test]
+    fn update_keeps_a_held_call_held() {
+        let mut state = State::new(channel(), channel());
+        let mut dg = Dialog::new(Direction::Outbound, "held".to_string(), "ours".to_string());
+        dg.state = DialogState::Confirmed;
+        dg.remote_tag = Some("theirs".to_string());
+        dg.on_hold = true;
+        state.get_dialogs().unwrap().push(dg);
+
+        let offer = get_sdp_with_direction("10.0.0.2", 1, "sendrecv");
+        let update = match SipMessage::try_from(format!(
+            "UPDATE sip:1004@10.0.0.1 SIP/2.0\r\nVia: SIP/2.0/UDP 10.0.0.2:5060;branch=z9hG4bKupd\r\nFrom: <sip:1002@sip.server.com>;tag=theirs\r\nTo: <sip:1004@sip.server.com>;tag=ours\r\nCall-ID: held\r\nCSeq: 3 UPDATE\r\nContent-Type: application/sdp\r\nContent-Length: {}\r\n\r\n{}",
+            offer.len(),
+            offer
+        ))
+        .unwrap()
+        {
+            SipMessage::Request(update) => update,
+            SipMessage::Response(_) => unreachable!(),
+        };
+
+        let answer = update_received(&update, &mut state, "10.0.0.1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(get_sdp_direction(answer.as_bytes()), "sendonly");
+    }
+}
@@ -1,27 +1,47 @@
 use crate::{
-    commands::{auth::Auth, auth::AuthModel, ok::ok, trying::trying},
-    config::JSONConfiguration,
+    commands::{
+        auth::Auth,
+        auth::AuthModel,
+        helper::{
+            get_other_header, get_route_set, get_sdp_media, get_tag, get_telephone_event_payload,
+            get_via_host, get_via_port,
+        },
+        ok::{ok, ok_with_sdp},
+        reject::reject,
+        ringing::ringing,
+        trying::trying,
+    },
+    composer::header_extension::CustomHeaderExtension,
+    config::{AnswerPolicy, JSONConfiguration},
+    flow::call::{
+        acknowledge_reinvite, bye, hang_up, info_received, prack_received, referred,
+        reinvite_received, transfer_progress, update_received,
+    },
+    flow::message::message_received,
+    flow::subscription::subscription_notified,
+    parsers::{has_option_tag, parse_replaces, session_interval_too_small},
     state::{
-        dialogs::{Direction, State},
-        options::SelfConfiguration,
+        dialogs::{Dialog, DialogState, Direction, State},
+        options::{SelfConfiguration, SipOptions},
+        transactions::{Transaction, TransactionState, TransactionType},
     },
     transmissions::sockets::{MpscBase, SocketV4},
 };
+use chrono::prelude::*;
+use rand::Rng;
 use rsip::{
     header_opt,
-    headers::ToTypedHeader,
+    headers::{ToTypedHeader, UntypedHeader},
     message::HasHeaders,
     message::HeadersExt,
     typed::{ProxyAuthenticate, Via, WwwAuthenticate},
-    Header, Request, Response, StatusCode,
+    Header, Request, Response, SipMessage, StatusCode,
 };
 use std::{
     convert::TryFrom,
-    net::IpAddr,
     sync::{Arc, Mutex},
-    thread,
-    time::Duration,
 };
+use uuid::Uuid;
 
 pub fn process_request_inbound(
     request: &Request,
@@ -30,26 +50,56 @@ pub fn process_request_inbound(
     settings: &mut SelfConfiguration,
 ) {
     let mut locked_state = state.lock().unwrap();
-    let channel = locked_state.get_sip_channel().unwrap();
 
     let via: Via = request.via_header().unwrap().typed().unwrap();
+    let call_id = request.call_id_header().unwrap().value().to_string();
 
     match request.method {
         rsip::Method::Register => {}
-        rsip::Method::Ack => {}
+        rsip::Method::Ack => {
+            let mut dialogs = locked_state.get_dialogs().unwrap();
+            for dg in dialogs.iter_mut() {
+                if dg.matches_request(request) {
+                    let mut transactions = dg.transactions.get_transactions().unwrap();
+                    for transaction in transactions.iter_mut() {
+                        if matches!(transaction.tr_type, TransactionType::InviteServer) {
+                            transaction.on_ack();
+                        }
+                    }
+                    if dg.state == DialogState::Early {
+                        dg.state = DialogState::Confirmed;
+                    }
+                    if dg.remote_media.is_none() {
+                        dg.remote_media = get_sdp_media(&request.body);
+                        dg.dtmf_payload = get_telephone_event_payload(&request.body);
+                    }
+                }
+            }
+        }
         rsip::Method::Bye => {
+            {
+                let mut dialogs = locked_state.get_dialogs().unwrap();
+                for dg in dialogs.iter_mut() {
+                    if dg.matches_request(request) {
+                        dg.state = DialogState::Terminated;
+                    }
+                }
+            }
+
+            let channel = locked_state.get_sip_channel().unwrap();
             channel
                 .0
                 .send(MpscBase {
                     event: Some(SocketV4 {
-                        ip: via.uri.host().to_string(),
-                        port: 5060,
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
                         bytes: ok(
                             conf,
                             &settings.ip.clone().to_string(),
                             request,
                             rsip::Method::Bye,
                             false,
+                            None,
                         )
                         .to_string()
                         .as_bytes()
@@ -59,38 +109,371 @@ pub fn process_request_inbound(
                 })
                 .unwrap();
         }
-        rsip::Method::Cancel => {}
-        rsip::Method::Info => {}
-        rsip::Method::Invite => {
-            // let connection: Option<IpAddr>;
+        rsip::Method::Cancel => {
+            let mut cancelled: Option<SipMessage> = None;
+            let mut found = false;
+            {
+                let mut dialogs = locked_state.get_dialogs().unwrap();
+                for dg in dialogs.iter_mut() {
+                    if !dg.matches_request(request) {
+                        continue;
+                    }
+                    let mut transactions = dg.transactions.get_transactions().unwrap();
+                    for transaction in transactions.iter_mut() {
+                        if !transaction.matches_cancel(request) {
+                            continue;
+                        }
+                        found = true;
 
+                        // too late if the INVITE already got its final response
+                        if transaction.state == TransactionState::Proceeding {
+                            if let Some(SipMessage::Request(invite)) = &transaction.remote {
+                                let terminated = reject(
+                                    invite,
+                                    StatusCode::RequestTerminated,
+                                    Some(&dg.local_tag),
+                                );
+                                transaction.local = Some(terminated.clone());
+                                transaction.start_server(&get_via_host(&via), get_via_port(&via));
+                                cancelled = Some(terminated);
+                            }
+                        }
+                    }
+                    if cancelled.is_some() {
+                        dg.answer_at = None;
+                        dg.state = DialogState::Terminated;
+                    }
+                }
+            }
+
+            let response = match found {
+                true => ok(
+                    conf,
+                    &settings.ip.clone().to_string(),
+                    request,
+                    rsip::Method::Cancel,
+                    false,
+                    None,
+                ),
+                false => reject(request, StatusCode::CallTransactionDoesNotExist, None),
+            };
+
+            let channel = locked_state.get_sip_channel().unwrap();
+            for message in std::iter::once(response).chain(cancelled) {
+                channel
+                    .0
+                    .send(MpscBase {
+                        event: Some(SocketV4 {
+                            ip: get_via_host(&via),
+                            port: get_via_port(&via),
+                            bytes: message.to_string().as_bytes().to_vec(),
+                        }),
+                        exit: false,
+                    })
+                    .unwrap();
+            }
+        }
+        rsip::Method::Info => {
+            let response = match info_received(request, &mut locked_state) {
+                StatusCode::OK => ok(
+                    conf,
+                    &settings.ip.clone().to_string(),
+                    request,
+                    rsip::Method::Info,
+                    false,
+                    None,
+                ),
+                code => reject(request, code, None),
+            };
+
+            let channel = locked_state.get_sip_channel().unwrap();
             channel
                 .0
                 .send(MpscBase {
                     event: Some(SocketV4 {
-                        ip: via.uri.host().to_string(),
-                        port: 5060,
-                        bytes: trying(conf, &settings.ip.clone().to_string(), request)
-                            .to_string()
-                            .as_bytes()
-                            .to_vec(),
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
+                        bytes: response.to_string().as_bytes().to_vec(),
                     }),
                     exit: false,
                 })
                 .unwrap();
-            thread::sleep(Duration::from_secs(1));
+        }
+        rsip::Method::Invite => {
+            // a retransmitted INVITE is absorbed by its server transaction,
+            // which repeats the last response sent instead
+            let mut retransmission = false;
+            let mut last_response: Option<SipMessage> = None;
+            let mut local_tag: Option<String> = None;
+            {
+                let mut dialogs = locked_state.get_dialogs().unwrap();
+                for dg in dialogs.iter_mut() {
+                    if dg.matches_request(request) {
+                        local_tag = Some(dg.local_tag.clone());
+                        let transactions = dg.transactions.get_transactions().unwrap();
+                        if let Some(transaction) =
+                            transactions.iter().find(|tr| tr.matches_request(request))
+                        {
+                            retransmission = true;
+                            last_response = transaction.local.clone();
+                        }
+                    }
+                }
+            }
+
+            if retransmission {
+                info!("absorbing INVITE retransmission for {}", call_id);
+                if let Some(response) = last_response {
+                    let channel = locked_state.get_sip_channel().unwrap();
+                    channel
+                        .0
+                        .send(MpscBase {
+                            event: Some(SocketV4 {
+                                ip: get_via_host(&via),
+                                port: get_via_port(&via),
+                                bytes: response.to_string().as_bytes().to_vec(),
+                            }),
+                            exit: false,
+                        })
+                        .unwrap();
+                }
+                return;
+            }
+
+            let local_tag = local_tag.unwrap_or_else(|| Uuid::new_v4().to_string());
+            let trying_response = trying(conf, request);
+            {
+                let mut dialogs = locked_state.get_dialogs().unwrap();
+
+                if !dialogs.iter().any(|dg| dg.matches_request(request)) {
+                    let from = request.from_header().unwrap().typed().unwrap();
+                    let to = request.to_header().unwrap().typed().unwrap();
+
+                    let mut dialog =
+                        Dialog::new(Direction::Inbound, call_id.clone(), local_tag.clone());
+                    dialog.remote_tag = get_tag(&from.params);
+                    dialog.local_uri = Some(to.uri.to_string());
+                    dialog.remote_uri = Some(from.uri.to_string());
+                    dialog.remote_target = request
+                        .contact_header()
+                        .ok()
+                        .and_then(|contact| contact.typed().ok())
+                        .map(|contact| contact.uri.to_string());
+                    dialog.route_set =
+                        get_route_set(request.headers.get_record_route_header_array());
+                    dialogs.push(dialog);
+                }
+
+                let dg = dialogs
+                    .iter_mut()
+                    .find(|dg| dg.matches_request(request))
+                    .unwrap();
+                let mut transaction = Transaction::new(
+                    TransactionType::InviteServer,
+                    SipOptions {
+                        branch: via.branch().map(|b| b.to_string()).unwrap_or_default(),
+                        extension: conf.extension.to_string(),
+                        username: conf.username.clone(),
+                        sip_server: conf.sip_server.to_string(),
+                        sip_port: conf.sip_port.to_string(),
+                        ip: settings.ip.to_string(),
+                        msg: None,
+                        cld: None,
+                        md5: None,
+                        nonce: None,
+                        opaque: None,
+                        call_id: call_id.clone(),
+                        tag_local: local_tag.clone(),
+                        tag_remote: dg.remote_tag.clone(),
+                        nc: None,
+                        cnonce: None,
+                        qop: false,
+                        realm: "".to_string(),
+                        local_port: conf.local_sip_port.to_string(),
+                        transport: conf.transport,
+                    },
+                    Some(trying_response.clone()),
+                );
+                transaction.remote = Some(request.clone().into());
+                dg.transactions
+                    .get_transactions()
+                    .unwrap()
+                    .push(transaction);
+            }
+
+            {
+                let channel = locked_state.get_sip_channel().unwrap();
+                channel
+                    .0
+                    .send(MpscBase {
+                        event: Some(SocketV4 {
+                            ip: get_via_host(&via),
+                            port: get_via_port(&via),
+                            bytes: trying_response.to_string().as_bytes().to_vec(),
+                        }),
+                        exit: false,
+                    })
+                    .unwrap();
+            }
+
+            // a re-INVITE within an established call gets answered right away
+            if let Some(answer) = reinvite_received(
+                conf,
+                request,
+                &mut locked_state,
+                &settings.ip.clone().to_string(),
+            ) {
+                let channel = locked_state.get_sip_channel().unwrap();
+                channel
+                    .0
+                    .send(MpscBase {
+                        event: Some(SocketV4 {
+                            ip: get_via_host(&via),
+                            port: get_via_port(&via),
+                            bytes: answer.to_string().as_bytes().to_vec(),
+                        }),
+                        exit: false,
+                    })
+                    .unwrap();
+                return;
+            }
+
+            // an INVITE with Replaces (RFC 3891) takes over an established call,
+            // it gets answered right away and the replaced call gets a BYE
+            let mut replaced: Option<SocketV4> = None;
+            let replaces = get_other_header(&request.headers, "Replaces")
+                .and_then(|value| parse_replaces(&value));
+            let policy = match replaces {
+                // too short a session interval (RFC 4028) gets a 422 with our Min-SE
+                _ if session_interval_too_small(&request.headers) => {
+                    AnswerPolicy::Reject { code: 422 }
+                }
+                Some((replaced_id, to_tag, from_tag)) => {
+                    let mut dialogs = locked_state.get_dialogs().unwrap();
+                    let target = dialogs.iter_mut().find(|dg| {
+                        dg.call_id == replaced_id
+                            && dg.local_tag == to_tag
+                            && dg.remote_tag.as_deref() == Some(from_tag.as_str())
+                            && dg.state == DialogState::Confirmed
+                    });
+                    match target {
+                        Some(dg) => {
+                            info!("{} replaces {}", call_id, dg.call_id);
+                            replaced = bye(dg);
+                            AnswerPolicy::Ring { ring: 0 }
+                        }
+                        None => AnswerPolicy::Reject { code: 481 },
+                    }
+                }
+                None => settings.answer.resolve(),
+            };
+
+            // a caller supporting 100rel gets a reliable 180, retransmitted until its PRACK
+            let rseq: Option<u32> = if has_option_tag(&request.headers, "Supported", "100rel")
+                || has_option_tag(&request.headers, "Require", "100rel")
+            {
+                Some(rand::thread_rng().gen_range(1, 1 << 31))
+            } else {
+                None
+            };
+
+            // the answer is sent by `answer_pending`, so ringing does not hold up
+            // the SIP loop and a CANCEL can still get in
+            let mut follow_up: Option<SipMessage> = None;
+            {
+                let mut dialogs = locked_state.get_dialogs().unwrap();
+                for dg in dialogs.iter_mut() {
+                    if !dg.matches_request(request) || dg.state != DialogState::Early {
+                        continue;
+                    }
+                    let mut transactions = dg.transactions.get_transactions().unwrap();
+                    let server = match transactions
+                        .iter_mut()
+                        .find(|tr| tr.matches_request(request))
+                    {
+                        Some(server) => server,
+                        None => continue,
+                    };
+
+                    match policy {
+                        AnswerPolicy::Reject { code } => {
+                            info!("rejecting {} with {}", call_id, code);
+                            let rejection = reject(request, code.into(), Some(&dg.local_tag));
+                            server.local = Some(rejection.clone());
+                            server.start_server(&get_via_host(&via), get_via_port(&via));
+                            dg.state = DialogState::Terminated;
+                            follow_up = Some(rejection);
+                        }
+                        AnswerPolicy::Ring { .. }
+                        | AnswerPolicy::Never
+                        | AnswerPolicy::Manual
+                        | AnswerPolicy::Percentage { .. } => {
+                            let ringing_response = ringing(
+                                conf,
+                                &settings.ip.to_string(),
+                                request,
+                                &dg.local_tag,
+                                rseq,
+                            );
+                            server.local = Some(ringing_response.clone());
+                            if rseq.is_some() {
+                                dg.rseq = rseq;
+                                server.start_reliable(&get_via_host(&via), get_via_port(&via));
+                            }
+                            if let AnswerPolicy::Ring { ring } = policy {
+                                dg.answer_at =
+                                    Some(Local::now() + chrono::Duration::seconds(ring as i64));
+                            }
+                            follow_up = Some(ringing_response);
+                        }
+                    }
+                    break;
+                }
+            }
+
+            if let Some(response) = follow_up {
+                let channel = locked_state.get_sip_channel().unwrap();
+                channel
+                    .0
+                    .send(MpscBase {
+                        event: Some(SocketV4 {
+                            ip: get_via_host(&via),
+                            port: get_via_port(&via),
+                            bytes: response.to_string().as_bytes().to_vec(),
+                        }),
+                        exit: false,
+                    })
+                    .unwrap();
+            }
+
+            if let Some(event) = replaced {
+                let channel = locked_state.get_sip_channel().unwrap();
+                channel
+                    .0
+                    .send(MpscBase {
+                        event: Some(event),
+                        exit: false,
+                    })
+                    .unwrap();
+            }
+        }
+        rsip::Method::Message => {
+            message_received(request, &mut locked_state);
+
+            let channel = locked_state.get_sip_channel().unwrap();
             channel
                 .0
                 .send(MpscBase {
                     event: Some(SocketV4 {
-                        ip: via.uri.host().to_string(),
-                        port: 5060,
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
                         bytes: ok(
                             conf,
                             &settings.ip.clone().to_string(),
                             request,
-                            rsip::Method::Invite,
-                            true,
+                            rsip::Method::Message,
+                            false,
+                            None,
                         )
                         .to_string()
                         .as_bytes()
@@ -99,51 +482,56 @@ pub fn process_request_inbound(
                     exit: false,
                 })
                 .unwrap();
+        }
+        rsip::Method::Notify => {
+            subscription_notified(request, &mut locked_state);
+            let finished = transfer_progress(request, &mut locked_state);
+            {
+                let channel = locked_state.get_sip_channel().unwrap();
+                channel
+                    .0
+                    .send(MpscBase {
+                        event: Some(SocketV4 {
+                            ip: get_via_host(&via),
+                            port: get_via_port(&via),
+                            bytes: ok(
+                                conf,
+                                &settings.ip.clone().to_string(),
+                                request,
+                                rsip::Method::Notify,
+                                false,
+                                None,
+                            )
+                            .to_string()
+                            .as_bytes()
+                            .to_vec(),
+                        }),
+                        exit: false,
+                    })
+                    .unwrap();
+            }
 
-            info!("{}", String::from_utf8_lossy(&request.body).to_string());
-            let sdp = sdp_rs::SessionDescription::try_from(
-                String::from_utf8_lossy(&request.body).to_string(),
-            );
-
-            let connection: Option<IpAddr> = Some(
-                sdp.clone()
-                    .unwrap()
-                    .connection
-                    .unwrap()
-                    .connection_address
-                    .base,
-            );
-            let rtp_port: Option<u16> =
-                Some(sdp.unwrap().media_descriptions.first().unwrap().media.port);
-
-            match connection.is_some() && rtp_port.is_some() {
-                true => {
-                    // START NEW THREAD ON THE ABOVE TO RECEIVE PACKETS
-                    // rtp::event_loop::rtp_event_loop(
-                    //     &settings.ip,
-                    //     49152,
-                    //     state.clone(),
-                    //     &connection.unwrap(),
-                    //     rtp_port.unwrap(),
-                    // );
-                }
-                false => {}
+            // the transferred call is over for us, whatever the outcome
+            if let Some(call_id) = finished {
+                drop(locked_state);
+                hang_up(&call_id, state.clone());
             }
         }
-        rsip::Method::Message => {}
-        rsip::Method::Notify => {
+        rsip::Method::Options => {
+            let channel = locked_state.get_sip_channel().unwrap();
             channel
                 .0
                 .send(MpscBase {
                     event: Some(SocketV4 {
-                        ip: via.uri.host().to_string(),
-                        port: 5060,
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
                         bytes: ok(
                             conf,
                             &settings.ip.clone().to_string(),
                             request,
-                            rsip::Method::Notify,
+                            rsip::Method::Options,
                             false,
+                            None,
                         )
                         .to_string()
                         .as_bytes()
@@ -153,33 +541,150 @@ pub fn process_request_inbound(
                 })
                 .unwrap();
         }
-        rsip::Method::Options => {
+        rsip::Method::PRack => {
+            let response = match prack_received(request, &mut locked_state) {
+                StatusCode::OK => ok(
+                    conf,
+                    &settings.ip.clone().to_string(),
+                    request,
+                    rsip::Method::PRack,
+                    false,
+                    None,
+                ),
+                code => reject(request, code, None),
+            };
+
+            let channel = locked_state.get_sip_channel().unwrap();
             channel
                 .0
                 .send(MpscBase {
                     event: Some(SocketV4 {
-                        ip: via.uri.host().to_string(),
-                        port: 5060,
-                        bytes: ok(
-                            conf,
-                            &settings.ip.clone().to_string(),
-                            request,
-                            rsip::Method::Options,
-                            false,
-                        )
-                        .to_string()
-                        .as_bytes()
-                        .to_vec(),
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
+                        bytes: response.to_string().as_bytes().to_vec(),
                     }),
                     exit: false,
                 })
                 .unwrap();
         }
-        rsip::Method::PRack => {}
         rsip::Method::Publish => {}
-        rsip::Method::Refer => {}
+        rsip::Method::Refer => {
+            drop(locked_state);
+            referred(request, conf, &settings.ip, state, &settings.verbosity);
+        }
         rsip::Method::Subscribe => {}
-        rsip::Method::Update => {}
+        rsip::Method::Update => {
+            let ip = settings.ip.to_string();
+            let response = match update_received(request, &mut locked_state, &ip) {
+                Ok(Some(answer)) => ok_with_sdp(conf, &ip, request, rsip::Method::Update, &answer),
+                Ok(None) => ok(conf, &ip, request, rsip::Method::Update, false, None),
+                Err(code) => reject(request, code, None),
+            };
+
+            let channel = locked_state.get_sip_channel().unwrap();
+            channel
+                .0
+                .send(MpscBase {
+                    event: Some(SocketV4 {
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
+                        bytes: response.to_string().as_bytes().to_vec(),
+                    }),
+                    exit: false,
+                })
+                .unwrap();
+        }
+    }
+}
+
+/// Answers the ringing inbound calls that are due
+pub fn answer_pending(
+    conf: &JSONConfiguration,
+    state: &Arc<Mutex<State>>,
+    settings: &SelfConfiguration,
+) {
+    let now = Local::now();
+    let mut answers: Vec<(Request, SipMessage)> = vec![];
+
+    let mut locked_state = state.lock().unwrap();
+    {
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+        for dg in dialogs.iter_mut() {
+            match dg.answer_at {
+                Some(answer_at) if answer_at <= now => dg.answer_at = None,
+                _ => continue,
+            }
+            if dg.state != DialogState::Early {
+                continue;
+            }
+
+            let mut answered: Option<Request> = None;
+            let mut transactions = dg.transactions.get_transactions().unwrap();
+            for transaction in transactions.iter_mut() {
+                if !matches!(transaction.tr_type, TransactionType::InviteServer)
+                    || transaction.state != TransactionState::Proceeding
+                {
+                    continue;
+                }
+                if let Some(SipMessage::Request(request)) = transaction.remote.clone() {
+                    answered = Some(request.clone());
+                    let via: Via = request.via_header().unwrap().typed().unwrap();
+                    let ok_response = ok(
+                        conf,
+                        &settings.ip.clone().to_string(),
+                        &request,
+                        rsip::Method::Invite,
+                        true,
+                        Some(&dg.local_tag),
+                    );
+                    transaction.local = Some(ok_response.clone());
+                    transaction.start_server(&get_via_host(&via), get_via_port(&via));
+                    dg.remote_media = get_sdp_media(&request.body);
+                    dg.dtmf_payload = get_telephone_event_payload(&request.body);
+                    answers.push((request, ok_response));
+                }
+            }
+            drop(transactions);
+
+            if let Some(request) = answered {
+                dg.refreshed_by(&request);
+            }
+        }
+    }
+
+    for (request, ok_response) in answers {
+        let via: Via = request.via_header().unwrap().typed().unwrap();
+        {
+            let channel = locked_state.get_sip_channel().unwrap();
+            channel
+                .0
+                .send(MpscBase {
+                    event: Some(SocketV4 {
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
+                        bytes: ok_response.to_string().as_bytes().to_vec(),
+                    }),
+                    exit: false,
+                })
+                .unwrap();
+        }
+
+        info!("{}", String::from_utf8_lossy(&request.body).to_string());
+        match get_sdp_media(&request.body) {
+            Some((_connection, _rtp_port)) => {
+                // START NEW THREAD ON THE ABOVE TO RECEIVE PACKETS
+                // rtp::event_loop::rtp_event_loop(
+                //     &settings.ip,
+                //     49152,
+                //     state.clone(),
+                //     &connection,
+                //     rtp_port,
+                //     &call_id,
+                // );
+            }
+            // late offer, the 200 OK carried our offer and the answer comes with the ACK
+            None => info!("no SDP in the INVITE, expecting the answer in the ACK"),
+        }
     }
 }
 
@@ -231,7 +736,13 @@ pub fn process_response_inbound(
                     for dg in registrations.iter_mut() {
                         if matches!(dg.diag_type, Direction::Inbound) {
                             let mut transactions = dg.transactions.get_transactions().unwrap();
-                            let mut local_transaction = transactions.first_mut().unwrap();
+                            let local_transaction = match transactions
+                                .iter_mut()
+                                .find(|tr| tr.matches_response(response))
+                            {
+                                Some(tr) => tr,
+                                None => continue,
+                            };
                             local_transaction.object.nonce = Some(auth_model.nonce.clone());
                             local_transaction.object.set_auth(
                                 conf,
@@ -240,12 +751,13 @@ pub fn process_response_inbound(
                             );
                             local_transaction.object.msg = local_transaction.local.clone();
 
-                            transaction = Some(
-                                local_transaction
-                                    .object
-                                    .push_auth_to_register(response.status_code.clone())
-                                    .to_string(),
-                            );
+                            let register = local_transaction
+                                .object
+                                .push_auth_to_register(response.status_code.clone());
+                            local_transaction.local = Some(register.clone());
+                            local_transaction.start(&conf.sip_server, conf.sip_port);
+
+                            transaction = Some(register.to_string());
                             break;
                         }
                     }
@@ -271,7 +783,11 @@ pub fn process_response_inbound(
             }
         }
         StatusCode::Trying => {}
-        StatusCode::OK => {}
-        _ => {}
+        StatusCode::OK => acknowledge_reinvite(response, state),
+        _ => {
+            if response.status_code.code() >= 300 {
+                acknowledge_reinvite(response, state);
+            }
+        }
     }
 }
@@ -0,0 +1,208 @@
+use crate::{
+    commands::helper::{get_base_uri, get_branch, get_content_type, is_sip_uri},
+    config::JSONConfiguration,
+    state::{
+        dialogs::{Dialog, DialogState, Direction, InstantMessage, State},
+        options::SipOptions,
+        transactions::{Transaction, TransactionType},
+    },
+    transmissions::sockets::{MpscBase, SocketV4},
+};
+use chrono::prelude::*;
+use rsip::{
+    prelude::{HeadersExt, ToTypedHeader},
+    Request, Response,
+};
+use std::{
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+use uuid::Uuid;
+
+/// Sends `text` in a page mode MESSAGE, it gets a dialog entry of its own so
+/// its response is matched like any other, a challenge included
+pub fn send_message(
+    conf: &JSONConfiguration,
+    ip: &IpAddr,
+    target: &str,
+    text: &str,
+    state: Arc<Mutex<State>>,
+) {
+    let target = if is_sip_uri(target) {
+        target.to_string()
+    } else {
+        get_base_uri(target, &conf.sip_server, &conf.sip_port.to_string()).to_string()
+    };
+
+    let options = SipOptions {
+        branch: get_branch(),
+        extension: conf.extension.to_string(),
+        username: conf.username.clone(),
+        sip_server: conf.sip_server.to_string(),
+        sip_port: conf.sip_port.to_string(),
+        ip: ip.to_string(),
+        msg: None,
+        cld: Some(target.clone()),
+        md5: None,
+        nonce: None,
+        opaque: None,
+        call_id: Uuid::new_v4().to_string(),
+        tag_local: Uuid::new_v4().to_string(),
+        tag_remote: None,
+        nc: None,
+        cnonce: None,
+        qop: false,
+        realm: "".to_string(),
+        local_port: conf.local_sip_port.to_string(),
+        transport: conf.transport,
+    };
+
+    let mut dialog = Dialog::new(
+        Direction::Outbound,
+        options.call_id.clone(),
+        options.tag_local.clone(),
+    );
+
+    let mut transaction = Transaction::new(
+        TransactionType::Typical,
+        options.clone(),
+        Some(options.create_message(text)),
+    );
+    transaction.object.msg = transaction.local.clone();
+    transaction.start(&conf.sip_server, conf.sip_port);
+
+    let message = transaction.local.clone().unwrap();
+    dialog
+        .transactions
+        .get_transactions()
+        .unwrap()
+        .push(transaction);
+
+    info!("sending message {} to {}", options.call_id, target);
+
+    let mut locked_state = state.lock().unwrap();
+    locked_state.get_dialogs().unwrap().push(dialog);
+
+    let channel = locked_state.get_sip_channel().unwrap();
+    channel
+        .0
+        .send(MpscBase {
+            event: Some(SocketV4 {
+                ip: conf.clone().sip_server,
+                port: conf.clone().sip_port,
+                bytes: message.to_string().as_bytes().to_vec(),
+            }),
+            exit: false,
+        })
+        .unwrap();
+}
+
+/// A MESSAGE has no dialog, it is over once answered,
+/// unless it gets repeated with credentials
+pub fn message_answered(dg: &mut Dialog, response: &Response) {
+    match response.status_code.code() {
+        401 | 407 => {}
+        200..=299 => {
+            info!("message {} delivered", dg.call_id);
+            dg.state = DialogState::Terminated;
+        }
+        code @ 300..=699 => {
+            warn!("message {} refused with {}", dg.call_id, code);
+            dg.state = DialogState::Terminated;
+        }
+        _ => {}
+    }
+}
+
+/// Keeps a received MESSAGE in the inbox
+pub fn message_received(request: &Request, state: &mut State) {
+    let from = request
+        .from_header()
+        .ok()
+        .and_then(|from| from.typed().ok())
+        .map(|from| from.uri.to_string())
+        .unwrap_or_default();
+
+    info!("message received from {}", from);
+
+    state.get_inbox().unwrap().push(InstantMessage {
+        from,
+        content_type: get_content_type(&request.headers),
+        body: String::from_utf8_lossy(&request.body).to_string(),
+        received: Local::now().to_rfc3339(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        flow::message::message_answered,
+        state::{
+            dialogs::{Dialog, DialogState, Direction, State},
+            transactions::{tests::options, Transaction, TransactionType},
+        },
+    };
+    use rsip::{SipMessage, StatusCode};
+    use std::{convert::TryFrom, sync::mpsc::channel};
+
+    const HEADERS: &str = "Via: SIP/2.0/UDP 10.0.0.1:5060;branch=z9hG4bKmessage\r\nFrom: <sip:1004@sip.server.com>;tag=ours\r\nTo: <sip:1002@sip.server.com>\r\nCall-ID: message\r\nCSeq: 1 MESSAGE\r\n";
+
+    /// Delivery status of a MESSAGE answered with `status_line`
+    fn answer(status_line: &str) -> (DialogState, Option<StatusCode>) {
+        let mut state = State::new(channel(), channel());
+        let message = SipMessage::try_from(format!(
+            "MESSAGE sip:1002@sip.server.com SIP/2.0\r\n{}Content-Type: text/plain\r\nContent-Length: 2\r\n\r\nhi",
+            HEADERS
+        ))
+        .unwrap();
+        let mut transaction = Transaction::new(TransactionType::Typical, options(), Some(message));
+        transaction.start("sip.server.com", 5060);
+        let mut dg = Dialog::new(
+            Direction::Outbound,
+            "message".to_string(),
+            "ours".to_string(),
+        );
+        dg.transactions
+            .get_transactions()
+            .unwrap()
+            .push(transaction);
+        state.get_dialogs().unwrap().push(dg);
+
+        let response = match SipMessage::try_from(format!(
+            "SIP/2.0 {}\r\n{}Content-Length: 0\r\n\r\n",
+            status_line, HEADERS
+        ))
+        .unwrap()
+        {
+            SipMessage::Response(response) => response,
+            SipMessage::Request(_) => unreachable!(),
+        };
+        state.update_transaction(&response, |dg, _, response| message_answered(dg, response));
+
+        let mut dialogs = state.get_dialogs().unwrap();
+        let result = dialogs[0].transactions.get_transactions().unwrap()[0]
+            .result
+            .clone();
+        (dialogs[0].state, result)
+    }
+
+    #[test]
+    fn delivery_follows_the_response() {
+        assert_eq!(
+            answer("200 OK"),
+            (DialogState::Terminated, Some(StatusCode::OK))
+        );
+        assert_eq!(
+            answer("404 Not Found"),
+            (DialogState::Terminated, Some(StatusCode::NotFound))
+        );
+        // challenged, the MESSAGE gets repeated with credentials
+        assert_eq!(
+            answer("407 Proxy Authentication Required"),
+            (
+                DialogState::Early,
+                Some(StatusCode::ProxyAuthenticationRequired)
+            )
+        );
+    }
+}
@@ -1,4 +1,10 @@
+/// Actions on an established call
+pub mod call;
 /// Inbound Flow
 pub mod inbound;
+/// Instant messages
+pub mod message;
 /// Outbound Flow
 pub mod outbound;
+/// Event subscriptions
+pub mod subscription;
@@ -2,21 +2,32 @@ use crate::{
     commands::{
         auth::Auth,
         auth::AuthModel,
-        helper::{get_address_from_contact, get_address_from_record_route, get_remote_tag},
-        ok::ok,
+        helper::{
+            get_address_from_contact, get_address_from_record_route, get_base_uri, get_branch,
+            get_dial_uri, get_other_header, get_route_set, get_sdp_media, get_tag,
+            get_telephone_event_payload, get_via_host, get_via_port,
+        },
+        ok::{ok, ok_with_sdp},
+        reject::reject,
     },
     composer::header_extension::CustomHeaderExtension,
     config::JSONConfiguration,
+    flow::call::{
+        bye, get_target, hang_up, info_received, prack_received, referred, reinvite_received,
+        transfer_progress, update_received,
+    },
+    flow::message::message_received,
+    flow::subscription::subscription_notified,
+    parsers::{get_min_se, has_option_tag},
     slog::udp_logger,
     state::{
-        dialogs::{Dialog, Direction, State, Transactions},
+        dialogs::{Dialog, DialogState, Direction, State},
         options::{SelfConfiguration, SipOptions, Verbosity},
         transactions::{Transaction, TransactionType},
     },
     transmissions::sockets::{MpscBase, SocketV4},
 };
 
-use chrono::prelude::*;
 use rsip::{
     header_opt,
     message::HasHeaders,
@@ -31,28 +42,22 @@ use std::{
 };
 use uuid::Uuid;
 
+/// Sets up the dialog of a call to `destination` and returns its call-id,
+/// `call_id` is used when the caller already handed one out, a new one otherwise
 pub fn outbound_configure(
     conf: &JSONConfiguration,
     ip: &IpAddr,
     destination: &str,
+    call_id: Option<String>,
     dialog_state: Arc<Mutex<State>>,
-) {
+) -> String {
     let mut locked_state = dialog_state.lock().unwrap();
     let mut dialogs = locked_state.get_dialogs().unwrap();
 
-    let call_id = Uuid::new_v4().to_string();
-    let now = Utc::now();
+    let call_id = call_id.unwrap_or_else(|| Uuid::new_v4().to_string());
 
     let invite = SipOptions {
-        branch: format!(
-            "z9hG4bK{}{}{}{}{}{}",
-            now.month(),
-            now.day(),
-            now.hour(),
-            now.minute(),
-            now.second(),
-            now.timestamp_millis()
-        ),
+        branch: get_branch(),
         extension: conf.extension.to_string(),
         username: conf.username.clone(),
         sip_server: conf.sip_server.to_string(),
@@ -70,37 +75,55 @@ pub fn outbound_configure(
         cnonce: None,
         qop: false,
         realm: "".to_string(),
+        local_port: conf.local_sip_port.to_string(),
+        transport: conf.transport,
     };
 
-    let dialog = Dialog {
-        call_id: call_id.clone(),
-        diag_type: Direction::Outbound,
-        local_tag: Uuid::new_v4().to_string(),
-        remote_tag: None,
-        transactions: Transactions::new(),
-        time: Local::now(),
-    };
+    let mut dialog = Dialog::new(
+        Direction::Outbound,
+        call_id.clone(),
+        invite.tag_local.clone(),
+    );
+    dialog.local_uri = Some(
+        get_base_uri(
+            &conf.extension,
+            &conf.sip_server,
+            &conf.sip_port.to_string(),
+        )
+        .to_string(),
+    );
+    dialog.remote_uri = Some(get_dial_uri(
+        destination,
+        &conf.sip_server,
+        &conf.sip_port.to_string(),
+        conf.transport,
+    ));
 
     dialogs.push(dialog);
 
     for dg in dialogs.iter_mut() {
         if dg.call_id == call_id {
             let mut transactions = dg.transactions.get_transactions().unwrap();
-            let mut transaction = Transaction {
-                object: invite.clone(),
-                local: Some(invite.set_initial_invite()),
-                remote: None,
-                tr_type: TransactionType::Invite,
-            };
+            let mut transaction = Transaction::new(
+                TransactionType::Invite,
+                invite.clone(),
+                Some(invite.set_initial_invite()),
+            );
             transaction.object.msg = Some(transaction.object.clone().set_initial_invite());
             transactions.push(transaction);
         }
     }
+
+    call_id
 }
 
 /// Sends the Intial invite for an outbound call
-// TODO pass identifier for the call
-pub fn outbound_start(conf: &JSONConfiguration, state: Arc<Mutex<State>>, vrb: &Verbosity) {
+pub fn outbound_start(
+    conf: &JSONConfiguration,
+    call_id: &str,
+    state: Arc<Mutex<State>>,
+    vrb: &Verbosity,
+) {
     let mut transaction: Option<String> = None;
     {
         let state: Arc<Mutex<State>> = state.clone();
@@ -108,8 +131,8 @@ pub fn outbound_start(conf: &JSONConfiguration, state: Arc<Mutex<State>>, vrb: &
         let mut dialogs = locked_state.get_dialogs().unwrap();
         info!("number of dialogs {}: ", dialogs.len());
 
-        for dg in dialogs.iter_mut().rev() {
-            if matches!(dg.diag_type, Direction::Outbound) {
+        for dg in dialogs.iter_mut() {
+            if dg.call_id == call_id {
                 let mut transactions = dg.transactions.get_transactions().unwrap();
 
                 udp_logger(
@@ -119,6 +142,7 @@ pub fn outbound_start(conf: &JSONConfiguration, state: Arc<Mutex<State>>, vrb: &
 
                 let mut loop_transaction = transactions.last_mut().unwrap();
                 loop_transaction.local = loop_transaction.object.set_initial_invite().into();
+                loop_transaction.start(&conf.sip_server, conf.sip_port);
 
                 transaction = Some(loop_transaction.local.clone().unwrap().to_string());
                 break;
@@ -156,74 +180,73 @@ pub fn ack_invite(
         let mut locked_state = state.lock().unwrap();
         let mut dialogs = locked_state.get_dialogs().unwrap();
 
-        for dg in dialogs.iter_mut().rev() {
-            if matches!(dg.diag_type, Direction::Outbound) {
-                let mut transactions = dg.transactions.get_transactions().unwrap();
-
-                let mut loop_transaction = transactions.last_mut().unwrap();
-                loop_transaction.local = loop_transaction.object.set_initial_invite().into();
+        for dg in dialogs.iter_mut() {
+            let mut transactions = dg.transactions.get_transactions().unwrap();
 
-                let hstr = response.clone().to_header().unwrap().to_string();
-                let remote_tag = get_remote_tag(&hstr);
-                let now = Utc::now();
-
-                let ack = SipOptions {
-                    branch: format!(
-                        "z9hG4bK{}{}{}{}{}{}",
-                        now.month(),
-                        now.day(),
-                        now.hour(),
-                        now.minute(),
-                        now.second(),
-                        now.timestamp_millis()
-                    ),
-                    extension: conf.extension.to_string(),
-                    username: conf.username.clone(),
-                    sip_server: conf.sip_server.to_string(),
-                    sip_port: conf.sip_port.to_string(),
-                    ip: settings.ip.to_string(),
-                    msg: None,
-                    cld: loop_transaction.object.cld.clone(),
-                    call_id: loop_transaction.object.call_id.clone(),
-                    tag_local: loop_transaction.object.tag_local.clone(),
-                    tag_remote: Some(remote_tag.to_string()),
-                    md5: None,
-                    nonce: None,
-                    nc: None,
-                    cnonce: None,
-                    qop: false,
-                    opaque: None,
-                    realm: "".to_string(),
+            let loop_transaction =
+                match transactions.iter().find(|tr| tr.matches_response(response)) {
+                    Some(loop_transaction) => loop_transaction.clone(),
+                    None => continue,
                 };
 
-                let via_from_invite = loop_transaction
-                    .local
-                    .as_ref()
-                    .unwrap()
-                    .via_header()
-                    .unwrap();
-                let cseq_count = loop_transaction
-                    .local
-                    .as_ref()
-                    .unwrap()
-                    .cseq_header()
-                    .unwrap();
+            if !matches!(loop_transaction.tr_type, TransactionType::Invite) {
+                break;
+            }
 
-                let mut ack_transaction = Transaction {
-                    object: ack.clone(),
-                    local: Some(ack.create_basic_ack(via_from_invite, cseq_count)),
-                    remote: None,
-                    tr_type: TransactionType::Ack,
-                };
+            let to_tag = response
+                .to_header()
+                .ok()
+                .and_then(|to| to.typed().ok())
+                .and_then(|to| get_tag(&to.params));
 
-                ack_transaction.object.msg = Some(
-                    ack_transaction
-                        .object
-                        .create_basic_ack(via_from_invite, cseq_count),
-                );
-                transactions.push(ack_transaction.clone());
-                transaction = Some(ack_transaction.local.as_ref().unwrap().to_string());
-            }
+            let ack = SipOptions {
+                branch: get_branch(),
+                extension: conf.extension.to_string(),
+                username: conf.username.clone(),
+                sip_server: conf.sip_server.to_string(),
+                sip_port: conf.sip_port.to_string(),
+                ip: settings.ip.to_string(),
+                msg: None,
+                cld: loop_transaction.object.cld.clone(),
+                call_id: loop_transaction.object.call_id.clone(),
+                tag_local: loop_transaction.object.tag_local.clone(),
+                tag_remote: Some(to_tag.unwrap_or_default()),
+                md5: None,
+                nonce: None,
+                nc: None,
+                cnonce: None,
+                qop: false,
+                opaque: None,
+                realm: "".to_string(),
+                local_port: conf.local_sip_port.to_string(),
+                transport: conf.transport,
+            };
+
+            // the ACK of a failed INVITE is part of the same transaction,
+            // it reuses the Via and the CSeq number of the request
+            let via_from_invite = loop_transaction
+                .local
+                .as_ref()
+                .unwrap()
+                .via_header()
+                .unwrap();
+            let cseq_count = loop_transaction
+                .local
+                .as_ref()
+                .unwrap()
+                .cseq_header()
+                .unwrap();
+
+            let mut ack_transaction = Transaction::new(
+                TransactionType::Ack,
+                ack.clone(),
+                Some(ack.create_basic_ack(via_from_invite, cseq_count)),
+            );
+            ack_transaction.object.msg = ack_transaction.local.clone();
+
+            transactions.push(ack_transaction.clone());
+            transaction = Some(ack_transaction.local.as_ref().unwrap().to_string());
+            break;
         }
     }
     if let Some(..) = transaction {
@@ -252,26 +275,142 @@ pub fn process_request_outbound(
     settings: &mut SelfConfiguration,
 ) {
     let mut locked_state = state.lock().unwrap();
-    let channel = locked_state.get_sip_channel().unwrap();
 
     let via: Via = request.via_header().unwrap().typed().unwrap();
 
     match request.method {
-        Method::Ack => todo!(),
+        Method::Ack => {
+            let mut dialogs = locked_state.get_dialogs().unwrap();
+            for dg in dialogs.iter_mut() {
+                if dg.matches_request(request) {
+                    let mut transactions = dg.transactions.get_transactions().unwrap();
+                    for transaction in transactions.iter_mut() {
+                        if matches!(transaction.tr_type, TransactionType::InviteServer) {
+                            transaction.on_ack();
+                        }
+                    }
+                }
+            }
+        }
         Method::Bye => {
-            settings.flow = Direction::Inbound;
+            {
+                let mut dialogs = locked_state.get_dialogs().unwrap();
+                for dg in dialogs.iter_mut() {
+                    if dg.matches_request(request) {
+                        dg.state = DialogState::Terminated;
+                    }
+                }
+            }
+
+            let channel = locked_state.get_sip_channel().unwrap();
             channel
                 .0
                 .send(MpscBase {
                     event: Some(SocketV4 {
-                        ip: via.uri.host().to_string(),
-                        port: 5060,
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
                         bytes: ok(
                             conf,
                             &settings.ip.clone().to_string(),
                             request,
                             rsip::Method::Bye,
                             false,
+                            None,
+                        )
+                        .to_string()
+                        .as_bytes()
+                        .to_vec(),
+                    }),
+                    exit: false,
+                })
+                .unwrap();
+        }
+        Method::Cancel => {
+            // nothing to cancel, outbound calls never have a pending INVITE from the other side
+            let channel = locked_state.get_sip_channel().unwrap();
+            channel
+                .0
+                .send(MpscBase {
+                    event: Some(SocketV4 {
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
+                        bytes: reject(request, StatusCode::CallTransactionDoesNotExist, None)
+                            .to_string()
+                            .as_bytes()
+                            .to_vec(),
+                    }),
+                    exit: false,
+                })
+                .unwrap();
+        }
+        Method::Info => {
+            let response = match info_received(request, &mut locked_state) {
+                StatusCode::OK => ok(
+                    conf,
+                    &settings.ip.clone().to_string(),
+                    request,
+                    rsip::Method::Info,
+                    false,
+                    None,
+                ),
+                code => reject(request, code, None),
+            };
+
+            let channel = locked_state.get_sip_channel().unwrap();
+            channel
+                .0
+                .send(MpscBase {
+                    event: Some(SocketV4 {
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
+                        bytes: response.to_string().as_bytes().to_vec(),
+                    }),
+                    exit: false,
+                })
+                .unwrap();
+        }
+        Method::Invite => {
+            // only a re-INVITE within the call, a media change or a session refresh
+            let response = match reinvite_received(
+                conf,
+                request,
+                &mut locked_state,
+                &settings.ip.clone().to_string(),
+            ) {
+                Some(answer) => answer,
+                None => reject(request, StatusCode::CallTransactionDoesNotExist, None),
+            };
+
+            let channel = locked_state.get_sip_channel().unwrap();
+            channel
+                .0
+                .send(MpscBase {
+                    event: Some(SocketV4 {
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
+                        bytes: response.to_string().as_bytes().to_vec(),
+                    }),
+                    exit: false,
+                })
+                .unwrap();
+        }
+        Method::Message => {
+            message_received(request, &mut locked_state);
+
+            let channel = locked_state.get_sip_channel().unwrap();
+            channel
+                .0
+                .send(MpscBase {
+                    event: Some(SocketV4 {
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
+                        bytes: ok(
+                            conf,
+                            &settings.ip.clone().to_string(),
+                            request,
+                            rsip::Method::Message,
+                            false,
+                            None,
                         )
                         .to_string()
                         .as_bytes()
@@ -281,24 +420,55 @@ pub fn process_request_outbound(
                 })
                 .unwrap();
         }
-        Method::Cancel => todo!(),
-        Method::Info => todo!(),
-        Method::Invite => todo!(),
-        Method::Message => todo!(),
-        Method::Notify => {}
+        Method::Notify => {
+            subscription_notified(request, &mut locked_state);
+            let finished = transfer_progress(request, &mut locked_state);
+            {
+                let channel = locked_state.get_sip_channel().unwrap();
+                channel
+                    .0
+                    .send(MpscBase {
+                        event: Some(SocketV4 {
+                            ip: get_via_host(&via),
+                            port: get_via_port(&via),
+                            bytes: ok(
+                                conf,
+                                &settings.ip.clone().to_string(),
+                                request,
+                                rsip::Method::Notify,
+                                false,
+                                None,
+                            )
+                            .to_string()
+                            .as_bytes()
+                            .to_vec(),
+                        }),
+                        exit: false,
+                    })
+                    .unwrap();
+            }
+
+            // the transferred call is over for us, whatever the outcome
+            if let Some(call_id) = finished {
+                drop(locked_state);
+                hang_up(&call_id, state.clone());
+            }
+        }
         Method::Options => {
+            let channel = locked_state.get_sip_channel().unwrap();
             channel
                 .0
                 .send(MpscBase {
                     event: Some(SocketV4 {
-                        ip: via.uri.host().to_string(),
-                        port: 5060,
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
                         bytes: ok(
                             conf,
                             &settings.ip.clone().to_string(),
                             request,
                             rsip::Method::Options,
                             false,
+                            None,
                         )
                         .to_string()
                         .as_bytes()
@@ -308,12 +478,60 @@ pub fn process_request_outbound(
                 })
                 .unwrap();
         }
-        Method::PRack => todo!(),
+        Method::PRack => {
+            let response = match prack_received(request, &mut locked_state) {
+                StatusCode::OK => ok(
+                    conf,
+                    &settings.ip.clone().to_string(),
+                    request,
+                    rsip::Method::PRack,
+                    false,
+                    None,
+                ),
+                code => reject(request, code, None),
+            };
+
+            let channel = locked_state.get_sip_channel().unwrap();
+            channel
+                .0
+                .send(MpscBase {
+                    event: Some(SocketV4 {
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
+                        bytes: response.to_string().as_bytes().to_vec(),
+                    }),
+                    exit: false,
+                })
+                .unwrap();
+        }
         Method::Publish => todo!(),
-        Method::Refer => todo!(),
+        Method::Refer => {
+            drop(locked_state);
+            referred(request, conf, &settings.ip, state, &settings.verbosity);
+        }
         Method::Register => todo!(),
         Method::Subscribe => todo!(),
-        Method::Update => todo!(),
+        Method::Update => {
+            let ip = settings.ip.to_string();
+            let response = match update_received(request, &mut locked_state, &ip) {
+                Ok(Some(answer)) => ok_with_sdp(conf, &ip, request, rsip::Method::Update, &answer),
+                Ok(None) => ok(conf, &ip, request, rsip::Method::Update, false, None),
+                Err(code) => reject(request, code, None),
+            };
+
+            let channel = locked_state.get_sip_channel().unwrap();
+            channel
+                .0
+                .send(MpscBase {
+                    event: Some(SocketV4 {
+                        ip: get_via_host(&via),
+                        port: get_via_port(&via),
+                        bytes: response.to_string().as_bytes().to_vec(),
+                    }),
+                    exit: false,
+                })
+                .unwrap();
+        }
     }
 }
 pub fn process_response_outbound(
@@ -356,39 +574,28 @@ pub fn process_response_outbound(
                         opaque: (www_auth_from_proxy).0.opaque,
                     }
                 };
-                let mut transaction: Option<String> = None;
-                {
-                    let state: Arc<Mutex<State>> = state.clone();
+                let transaction = {
                     let mut locked_state = state.lock().unwrap();
-                    let mut dialogs = locked_state.get_dialogs().unwrap();
-
-                    for dg in dialogs.iter_mut().rev() {
-                        if matches!(dg.diag_type, Direction::Outbound) {
-                            let mut transactions = dg.transactions.get_transactions().unwrap();
-
-                            let tr_len = transactions.clone().len();
-
-                            let mut loop_transaction = transactions.get_mut(tr_len - 2).unwrap();
-                            if loop_transaction.object.nonce.is_some() {
-                                break;
-                            }
-
-                            loop_transaction.object.nonce = Some(auth_model.nonce.clone());
-                            loop_transaction
-                                .object
-                                .set_auth(conf, "INVITE", &auth_model.clone());
-                            loop_transaction.object.msg =
-                                Some(loop_transaction.local.clone().unwrap());
-                            transaction = Some(
-                                loop_transaction
-                                    .object
-                                    .push_auth_to_invite(response.status_code.clone())
-                                    .to_string(),
-                            );
-                            break;
-                        }
-                    }
-                }
+                    let found = push_auth(
+                        locked_state.get_dialogs().unwrap().iter_mut(),
+                        response,
+                        conf,
+                        &auth_model,
+                    );
+                    // subscriptions get challenged the same way calls do
+                    found.or_else(|| {
+                        push_auth(
+                            locked_state
+                                .get_subscriptions()
+                                .unwrap()
+                                .iter_mut()
+                                .map(|subscription| &mut subscription.dialog),
+                            response,
+                            conf,
+                            &auth_model,
+                        )
+                    })
+                };
                 if let Some(..) = transaction {
                     let state = state.clone();
                     let mut locked_state = state.lock().unwrap();
@@ -413,17 +620,21 @@ pub fn process_response_outbound(
             let mut locked_state = state.lock().unwrap();
             let mut dialogs = locked_state.get_dialogs().unwrap();
 
-            for dg in dialogs.iter_mut().rev() {
-                if matches!(dg.diag_type, Direction::Outbound) {
-                    let mut transactions = dg.transactions.get_transactions().unwrap();
-                    let mut transaction = transactions.last_mut().unwrap();
+            for dg in dialogs.iter_mut() {
+                let mut transactions = dg.transactions.get_transactions().unwrap();
+                if let Some(transaction) = transactions
+                    .iter_mut()
+                    .find(|tr| tr.matches_response(response))
+                {
                     transaction.remote = Some(SipMessage::Response(response.clone()));
                     break;
                 }
             }
+            drop(dialogs);
+            drop(locked_state);
+            prack(response, &state);
         }
-        StatusCode::BusyHere => {}
-        StatusCode::SessionProgress => {}
+        StatusCode::SessionProgress => prack(response, state),
         StatusCode::OK => {
             let mut transaction: Option<String> = None;
             let connection: Option<IpAddr>;
@@ -434,113 +645,130 @@ pub fn process_response_outbound(
                 let mut locked_state = state.lock().unwrap();
                 let mut dialogs = locked_state.get_dialogs().unwrap();
 
-                for dg in dialogs.iter_mut().rev() {
-                    if matches!(dg.diag_type, Direction::Outbound) {
-                        let mut transactions = dg.transactions.get_transactions().unwrap();
-                        let loop_transaction = transactions.last().unwrap();
-                        if loop_transaction.local.is_some() && loop_transaction.remote.is_some() {
-                            let hstr = response.clone().to_header().unwrap().to_string();
-
-                            info!("{}", String::from_utf8_lossy(&response.body).to_string());
-                            let sdp = sdp_rs::SessionDescription::try_from(
-                                String::from_utf8_lossy(&response.body).to_string(),
-                            );
-
-                            connection = Some(
-                                sdp.clone()
-                                    .unwrap()
-                                    .connection
-                                    .unwrap()
-                                    .connection_address
-                                    .base,
-                            );
-                            rtp_port =
-                                Some(sdp.unwrap().media_descriptions.first().unwrap().media.port);
-
-                            match connection.is_some() && rtp_port.is_some() {
-                                true => {
-                                    // START NEW THREAD ON THE ABOVE TO RECEIVE PACKETS
-                                    // rtp::event_loop::rtp_event_loop(
-                                    //     &settings.ip,
-                                    //     49152,
-                                    //     state.clone(),
-                                    //     &connection.unwrap(),
-                                    //     rtp_port.unwrap(),
-                                    // );
-                                }
-                                false => {}
+                for dg in dialogs.iter_mut() {
+                    let mut transactions = dg.transactions.get_transactions().unwrap();
+
+                    let loop_transaction =
+                        match transactions.iter().find(|tr| tr.matches_response(response)) {
+                            Some(loop_transaction) => loop_transaction.clone(),
+                            None => continue,
+                        };
+
+                    if matches!(loop_transaction.tr_type, TransactionType::Invite) {
+                        let to = match response.to_header().ok().and_then(|to| to.typed().ok()) {
+                            Some(to) => to,
+                            None => {
+                                warn!("2xx to the INVITE of {} has no valid To", dg.call_id);
+                                break;
                             }
+                        };
+                        let contact = match response.contact_header() {
+                            Ok(contact) => contact,
+                            Err(_) => {
+                                warn!("2xx to the INVITE of {} has no Contact", dg.call_id);
+                                break;
+                            }
+                        };
+                        let remote_tag = get_tag(&to.params);
 
-                            let remote_tag = get_remote_tag(&hstr);
-                            let now = Utc::now();
-
-                            let ack = SipOptions {
-                                branch: format!(
-                                    "z9hG4bK{}{}{}{}{}{}",
-                                    now.month(),
-                                    now.day(),
-                                    now.hour(),
-                                    now.minute(),
-                                    now.second(),
-                                    now.timestamp_millis()
-                                ),
-                                extension: conf.extension.to_string(),
-                                username: conf.username.clone(),
-                                sip_server: conf.sip_server.to_string(),
-                                sip_port: conf.sip_port.to_string(),
-                                ip: settings.ip.to_string(),
-                                msg: None,
-                                cld: loop_transaction.object.cld.clone(),
-                                call_id: loop_transaction.object.call_id.clone(),
-                                tag_local: loop_transaction.object.tag_local.clone(),
-                                tag_remote: Some(remote_tag.to_string()),
-                                md5: None,
-                                nonce: None,
-                                nc: None,
-                                cnonce: None,
-                                qop: false,
-                                opaque: None,
-                                realm: "".to_string(),
-                            };
-
-                            let via_from_invite = loop_transaction
-                                .local
-                                .as_ref()
-                                .unwrap()
-                                .via_header()
-                                .unwrap();
-                            let cseq_count = loop_transaction
-                                .local
-                                .as_ref()
-                                .unwrap()
-                                .cseq_header()
-                                .unwrap();
-                            let contact = response.contact_header().unwrap();
-
-                            let mut ack_transaction = Transaction {
-                                object: ack.clone(),
-                                local: Some(ack.create_ack(
-                                    via_from_invite,
-                                    response.headers.get_record_route_header_array().clone(),
-                                    contact,
-                                    cseq_count,
-                                )),
-                                remote: None,
-                                tr_type: TransactionType::Ack,
-                            };
-
-                            ack_transaction.object.msg = Some(ack_transaction.object.create_ack(
-                                via_from_invite,
+                        dg.remote_tag = remote_tag.clone();
+                        dg.remote_target = response
+                            .contact_header()
+                            .ok()
+                            .and_then(|contact| contact.typed().ok())
+                            .map(|contact| contact.uri.to_string());
+                        dg.route_set =
+                            get_route_set(response.headers.get_record_route_header_array())
+                                .into_iter()
+                                .rev()
+                                .collect();
+                        dg.state = DialogState::Confirmed;
+                        if let Some(SipMessage::Request(invite)) = &loop_transaction.local {
+                            dg.offer_answered(&invite.body);
+                        }
+
+                        info!("{}", String::from_utf8_lossy(&response.body).to_string());
+                        let sdp = match sdp_rs::SessionDescription::try_from(
+                            String::from_utf8_lossy(&response.body).to_string(),
+                        ) {
+                            Ok(sdp) => Some(sdp),
+                            Err(err) => {
+                                warn!("answer of {} has no valid SDP: {:?}", dg.call_id, err);
+                                None
+                            }
+                        };
+
+                        connection = sdp
+                            .as_ref()
+                            .and_then(|sdp| sdp.connection.as_ref())
+                            .map(|connection| connection.connection_address.base);
+                        rtp_port = sdp
+                            .as_ref()
+                            .and_then(|sdp| sdp.media_descriptions.first())
+                            .map(|media| media.media.port);
+                        dg.remote_media = get_sdp_media(&response.body);
+                        dg.dtmf_payload = get_telephone_event_payload(&response.body);
+
+                        match connection.is_some() && rtp_port.is_some() {
+                            true => {
+                                // START NEW THREAD ON THE ABOVE TO RECEIVE PACKETS
+                                // rtp::event_loop::rtp_event_loop(
+                                //     &settings.ip,
+                                //     49152,
+                                //     state.clone(),
+                                //     &connection.unwrap(),
+                                //     rtp_port.unwrap(),
+                                //     &call_id,
+                                // );
+                            }
+                            false => {}
+                        }
+
+                        let ack = SipOptions {
+                            branch: get_branch(),
+                            extension: conf.extension.to_string(),
+                            username: conf.username.clone(),
+                            sip_server: conf.sip_server.to_string(),
+                            sip_port: conf.sip_port.to_string(),
+                            ip: settings.ip.to_string(),
+                            msg: None,
+                            cld: loop_transaction.object.cld.clone(),
+                            call_id: loop_transaction.object.call_id.clone(),
+                            tag_local: loop_transaction.object.tag_local.clone(),
+                            tag_remote: remote_tag,
+                            md5: None,
+                            nonce: None,
+                            nc: None,
+                            cnonce: None,
+                            qop: false,
+                            opaque: None,
+                            realm: "".to_string(),
+                            local_port: conf.local_sip_port.to_string(),
+                            transport: conf.transport,
+                        };
+
+                        let cseq_count = loop_transaction
+                            .local
+                            .as_ref()
+                            .unwrap()
+                            .cseq_header()
+                            .unwrap();
+
+                        let mut ack_transaction = Transaction::new(
+                            TransactionType::Ack,
+                            ack.clone(),
+                            Some(ack.create_ack(
                                 response.headers.get_record_route_header_array().clone(),
                                 contact,
                                 cseq_count,
-                            ));
+                            )),
+                        );
+                        ack_transaction.object.msg = ack_transaction.local.clone();
 
-                            transactions.push(ack_transaction.clone());
-                            transaction = Some(ack_transaction.local.as_ref().unwrap().to_string());
-                        }
-                        break;
+                        transactions.push(ack_transaction.clone());
+                        transaction = Some(ack_transaction.local.as_ref().unwrap().to_string());
                     }
+                    break;
                 }
             }
             if let Some(..) = transaction {
@@ -573,12 +801,294 @@ pub fn process_response_outbound(
                     .unwrap();
             }
         }
-        StatusCode::ServerTimeOut => {
-            info!("something is a bit slow, getting a timeout");
+        // a 422 (RFC 4028) asks for a longer session interval, the INVITE goes out again
+        _ if response.status_code.code() == 422 => {
+            ack_invite(response, conf, state, settings);
+
+            let mut locked_state = state.lock().unwrap();
+            let request = push_min_se(&mut locked_state.get_dialogs().unwrap(), response, conf);
+            if let Some(request) = request {
+                let channel = locked_state.get_sip_channel().unwrap();
+                channel
+                    .0
+                    .send(MpscBase {
+                        event: Some(SocketV4 {
+                            ip: conf.clone().sip_server,
+                            port: conf.clone().sip_port,
+                            bytes: request.as_bytes().to_vec(),
+                        }),
+                        exit: false,
+                    })
+                    .unwrap();
+            }
         }
-        StatusCode::RequestTimeout => {
+        // a timeout ends the call, an established one its re-INVITE timed out in as well
+        StatusCode::ServerTimeOut | StatusCode::RequestTimeout => {
             info!("something is a bit slow, getting a timeout");
+            invite_failed(response, conf, state, settings, true);
         }
-        _ => todo!(),
+        _ => {
+            if (101..200).contains(&response.status_code.code()) {
+                prack(response, state);
+            }
+            // any other final response ends the call the INVITE was trying to set up
+            if response.status_code.code() >= 300 {
+                invite_failed(response, conf, state, settings, false);
+            }
+        }
+    }
+}
+
+/// ACKs a failed INVITE and ends the call it was setting up, a failed re-INVITE
+/// leaves the established call as it was, unless `end_call` says otherwise
+fn invite_failed(
+    response: &Response,
+    conf: &JSONConfiguration,
+    state: &Arc<Mutex<State>>,
+    settings: &mut SelfConfiguration,
+    end_call: bool,
+) {
+    ack_invite(response, conf, state, settings);
+
+    let mut event: Option<SocketV4> = None;
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+
+        for dg in dialogs.iter_mut() {
+            let is_invite = dg
+                .transactions
+                .get_transactions()
+                .unwrap()
+                .iter()
+                .any(|tr| {
+                    matches!(tr.tr_type, TransactionType::Invite) && tr.matches_response(response)
+                });
+            if !is_invite {
+                continue;
+            }
+
+            match dg.state {
+                DialogState::Early => {
+                    info!("call {} failed with {}", dg.call_id, response.status_code);
+                    dg.state = DialogState::Terminated;
+                }
+                DialogState::Confirmed if end_call => {
+                    info!(
+                        "re-INVITE on {} failed with {}, hanging up",
+                        dg.call_id, response.status_code
+                    );
+                    event = bye(dg);
+                }
+                _ => {}
+            }
+            break;
+        }
+    }
+
+    if let Some(event) = event {
+        let mut locked_state = state.lock().unwrap();
+        let channel = locked_state.get_sip_channel().unwrap();
+        channel
+            .0
+            .send(MpscBase {
+                event: Some(event),
+                exit: false,
+            })
+            .unwrap();
+    }
+}
+
+/// Acknowledges a reliable provisional response (RFC 3262) with a PRACK,
+/// the first one also sets up the early dialog
+fn prack(response: &Response, state: &Arc<Mutex<State>>) {
+    if !has_option_tag(&response.headers, "Require", "100rel") {
+        return;
+    }
+    let rseq = match get_other_header(&response.headers, "RSeq")
+        .and_then(|rseq| rseq.trim().parse::<u32>().ok())
+    {
+        Some(rseq) => rseq,
+        None => return,
+    };
+    let invite_cseq = response.cseq_header().unwrap().typed().unwrap().seq;
+
+    let mut event: Option<SocketV4> = None;
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut dialogs = locked_state.get_dialogs().unwrap();
+
+        for dg in dialogs.iter_mut() {
+            let is_invite = dg
+                .transactions
+                .get_transactions()
+                .unwrap()
+                .iter()
+                .any(|tr| {
+                    matches!(tr.tr_type, TransactionType::Invite) && tr.matches_response(response)
+                });
+            if !is_invite || dg.state != DialogState::Early {
+                continue;
+            }
+            // a retransmission, or one overtaken by a later provisional response
+            if dg.rseq.map_or(false, |last| rseq <= last) {
+                break;
+            }
+
+            if dg.remote_tag.is_none() {
+                let to = response.to_header().unwrap().typed().unwrap();
+                dg.remote_tag = get_tag(&to.params);
+                dg.remote_target = response
+                    .contact_header()
+                    .ok()
+                    .and_then(|contact| contact.typed().ok())
+                    .map(|contact| contact.uri.to_string());
+                dg.route_set = get_route_set(response.headers.get_record_route_header_array())
+                    .into_iter()
+                    .rev()
+                    .collect();
+            }
+
+            let (object, destination) = match get_target(dg) {
+                Some(target) => target,
+                None => break,
+            };
+            let cseq = dg.next_cseq();
+            let mut transaction = Transaction::new(
+                TransactionType::Typical,
+                object.clone(),
+                Some(object.create_prack(dg, cseq, rseq, invite_cseq)),
+            );
+            transaction.start(&destination.0, destination.1);
+            dg.rseq = Some(rseq);
+
+            info!(
+                "acknowledging provisional response {} on {}",
+                rseq, dg.call_id
+            );
+            event = Some(SocketV4 {
+                ip: destination.0,
+                port: destination.1,
+                bytes: transaction
+                    .local
+                    .as_ref()
+                    .unwrap()
+                    .to_string()
+                    .as_bytes()
+                    .to_vec(),
+            });
+            dg.transactions
+                .get_transactions()
+                .unwrap()
+                .push(transaction);
+            break;
+        }
+    }
+
+    if let Some(event) = event {
+        let mut locked_state = state.lock().unwrap();
+        let channel = locked_state.get_sip_channel().unwrap();
+        channel
+            .0
+            .send(MpscBase {
+                event: Some(event),
+                exit: false,
+            })
+            .unwrap();
+    }
+}
+
+/// Repeats the request a 401 or 407 challenged with credentials, once,
+/// returns the new request when one of `dialogs` sent the challenged one
+fn push_auth<'a>(
+    dialogs: impl IntoIterator<Item = &'a mut Dialog>,
+    response: &Response,
+    conf: &JSONConfiguration,
+    auth_model: &AuthModel,
+) -> Option<String> {
+    for dg in dialogs {
+        let mut transactions = dg.transactions.get_transactions().unwrap();
+
+        if let Some(loop_transaction) = transactions
+            .iter_mut()
+            .find(|tr| tr.matches_response(response))
+        {
+            let method = match loop_transaction.tr_type {
+                TransactionType::Invite => Method::Invite,
+                _ => match loop_transaction.method() {
+                    Some(method @ (Method::Message | Method::Subscribe)) => method,
+                    _ => return None,
+                },
+            };
+            if loop_transaction.object.nonce.is_some() {
+                return None;
+            }
+
+            loop_transaction.object.nonce = Some(auth_model.nonce.clone());
+            loop_transaction
+                .object
+                .set_auth(conf, &method.to_string(), &auth_model.clone());
+            loop_transaction.object.msg = Some(loop_transaction.local.clone().unwrap());
+            let code = response.status_code.clone();
+            let request = match method {
+                Method::Message => loop_transaction.object.push_auth_to_message(code),
+                Method::Subscribe => loop_transaction.object.push_auth_to_subscribe(code),
+                _ => loop_transaction.object.push_auth_to_invite(code),
+            };
+            loop_transaction.local = Some(request.clone());
+            loop_transaction.start(&conf.sip_server, conf.sip_port);
+
+            return Some(request.to_string());
+        }
+    }
+    None
+}
+
+/// Repeats the INVITE a 422 turned down with the Min-SE the 422 asks for, the call
+/// fails when the INVITE already offered that much, returns the new request
+fn push_min_se(
+    dialogs: &mut [Dialog],
+    response: &Response,
+    conf: &JSONConfiguration,
+) -> Option<String> {
+    let min_se = get_min_se(&response.headers);
+
+    for dg in dialogs.iter_mut() {
+        let mut transactions = dg.transactions.get_transactions().unwrap();
+        let invite = match transactions.iter_mut().find(|tr| {
+            matches!(tr.tr_type, TransactionType::Invite) && tr.matches_response(response)
+        }) {
+            Some(invite) => invite,
+            None => continue,
+        };
+
+        // a 422 to a refresh leaves the established call as it was
+        if dg.state != DialogState::Early {
+            return None;
+        }
+        let offered = invite
+            .local
+            .as_ref()
+            .and_then(|local| get_min_se(local.headers()));
+        let min_se = match min_se {
+            Some(min_se) if Some(min_se) > offered => min_se,
+            _ => {
+                info!("call {} failed with {}", dg.call_id, response.status_code);
+                dg.state = DialogState::Terminated;
+                return None;
+            }
+        };
+
+        info!(
+            "retrying {} with a session interval of {}",
+            dg.call_id, min_se
+        );
+        invite.object.msg = invite.local.clone();
+        let request = invite.object.push_min_se_to_invite(min_se);
+        invite.local = Some(request.clone());
+        invite.start(&conf.sip_server, conf.sip_port);
+
+        return Some(request.to_string());
     }
+    None
 }
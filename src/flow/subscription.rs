@@ -0,0 +1,307 @@
+use crate::{
+    commands::helper::{
+        get_base_uri, get_branch, get_other_header, get_route_set, get_tag, is_sip_uri,
+    },
+    composer::header_extension::CustomHeaderExtension,
+    config::JSONConfiguration,
+    flow::call::get_target,
+    parsers::{get_line_state, get_message_summary, get_user_from_uri},
+    state::{
+        dialogs::{Dialog, DialogState, Direction, LineState, State, Subscription},
+        options::SipOptions,
+        transactions::{Transaction, TransactionType},
+    },
+    transmissions::sockets::{MpscBase, SocketV4},
+};
+use chrono::prelude::*;
+use rsip::{
+    prelude::{HeadersExt, ToTypedHeader, UntypedHeader},
+    Request, Response,
+};
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+use uuid::Uuid;
+
+/// Lifetime we ask for, the notifier may grant a shorter one
+const EXPIRES: u32 = 3600;
+
+/// Subscribes to `event` of `resource`, a number or a `sip:` or `sips:` uri, the subscription
+/// is a dialog of its own and gets refreshed by `refresh_subscriptions`
+pub fn subscribe(
+    conf: &JSONConfiguration,
+    ip: &IpAddr,
+    event: &str,
+    resource: &str,
+    state: Arc<Mutex<State>>,
+) {
+    let target = if is_sip_uri(resource) {
+        resource.to_string()
+    } else {
+        get_base_uri(resource, &conf.sip_server, &conf.sip_port.to_string()).to_string()
+    };
+
+    let options = SipOptions {
+        branch: get_branch(),
+        extension: conf.extension.to_string(),
+        username: conf.username.clone(),
+        sip_server: conf.sip_server.to_string(),
+        sip_port: conf.sip_port.to_string(),
+        ip: ip.to_string(),
+        msg: None,
+        cld: Some(target.clone()),
+        md5: None,
+        nonce: None,
+        opaque: None,
+        call_id: Uuid::new_v4().to_string(),
+        tag_local: Uuid::new_v4().to_string(),
+        tag_remote: None,
+        nc: None,
+        cnonce: None,
+        qop: false,
+        realm: "".to_string(),
+        local_port: conf.local_sip_port.to_string(),
+        transport: conf.transport,
+    };
+
+    let mut dialog = Dialog::new(
+        Direction::Outbound,
+        options.call_id.clone(),
+        options.tag_local.clone(),
+    );
+    dialog.local_uri = Some(
+        get_base_uri(
+            &conf.extension,
+            &conf.sip_server,
+            &conf.sip_port.to_string(),
+        )
+        .to_string(),
+    );
+    dialog.remote_uri = Some(target.clone());
+
+    let mut transaction = Transaction::new(
+        TransactionType::Typical,
+        options.clone(),
+        Some(options.create_subscribe(event, EXPIRES)),
+    );
+    transaction.object.msg = transaction.local.clone();
+    transaction.start(&conf.sip_server, conf.sip_port);
+
+    let subscribe = transaction.local.clone().unwrap();
+    dialog
+        .transactions
+        .get_transactions()
+        .unwrap()
+        .push(transaction);
+
+    info!("subscribing to {} of {}", event, target);
+
+    let mut locked_state = state.lock().unwrap();
+    locked_state
+        .get_subscriptions()
+        .unwrap()
+        .push(Subscription::new(event, dialog));
+
+    let channel = locked_state.get_sip_channel().unwrap();
+    channel
+        .0
+        .send(MpscBase {
+            event: Some(SocketV4 {
+                ip: conf.clone().sip_server,
+                port: conf.clone().sip_port,
+                bytes: subscribe.to_string().as_bytes().to_vec(),
+            }),
+            exit: false,
+        })
+        .unwrap();
+}
+
+/// Confirms a subscription dialog with the 2xx to its SUBSCRIBE and schedules
+/// the refresh ahead of the expiry the notifier granted
+pub fn subscribe_answered(dg: &mut Dialog, response: &Response) {
+    match response.status_code.code() {
+        200..=299 => {
+            let expires = response
+                .expires_header()
+                .and_then(|expires| expires.value().parse::<u32>().ok())
+                .unwrap_or(EXPIRES);
+
+            if expires == 0 {
+                info!("subscription {} ended", dg.call_id);
+                dg.state = DialogState::Terminated;
+                dg.refresh_at = None;
+                return;
+            }
+
+            if dg.state == DialogState::Early {
+                let to = response.to_header().unwrap().typed().unwrap();
+                dg.remote_tag = dg.remote_tag.clone().or_else(|| get_tag(&to.params));
+                dg.remote_target = response
+                    .contact_header()
+                    .ok()
+                    .and_then(|contact| contact.typed().ok())
+                    .map(|contact| contact.uri.to_string())
+                    .or_else(|| dg.remote_target.clone());
+                dg.route_set = get_route_set(response.headers.get_record_route_header_array())
+                    .into_iter()
+                    .rev()
+                    .collect();
+                dg.state = DialogState::Confirmed;
+            }
+
+            dg.refresh_at = Some(Local::now() + chrono::Duration::seconds(expires as i64 * 9 / 10));
+        }
+        401 | 407 => {}
+        code @ 300..=699 => {
+            warn!("subscription {} refused with {}", dg.call_id, code);
+            dg.state = DialogState::Terminated;
+            dg.refresh_at = None;
+        }
+        _ => {}
+    }
+}
+
+/// Refreshes every subscription that is about to expire
+pub fn refresh_subscriptions(state: &Arc<Mutex<State>>) {
+    let now = Local::now();
+    let mut transactions: Vec<SocketV4> = vec![];
+    {
+        let mut locked_state = state.lock().unwrap();
+        let mut subscriptions = locked_state.get_subscriptions().unwrap();
+
+        for subscription in subscriptions.iter_mut() {
+            let dg = &mut subscription.dialog;
+            if dg.state != DialogState::Confirmed
+                || dg.refresh_at.map_or(true, |refresh_at| refresh_at > now)
+            {
+                continue;
+            }
+
+            let (mut object, destination) = match get_target(dg) {
+                Some(target) => target,
+                None => continue,
+            };
+            // a refresh may get challenged again
+            object.nonce = None;
+
+            let cseq = dg.next_cseq();
+            let mut refresh = Transaction::new(
+                TransactionType::Typical,
+                object.clone(),
+                Some(object.create_resubscribe(dg, &subscription.event, cseq, EXPIRES)),
+            );
+            refresh.object.msg = refresh.local.clone();
+            refresh.start(&destination.0, destination.1);
+            dg.refresh_at = None;
+
+            info!("refreshing subscription {}", dg.call_id);
+            transactions.push(SocketV4 {
+                ip: destination.0,
+                port: destination.1,
+                bytes: refresh
+                    .local
+                    .as_ref()
+                    .unwrap()
+                    .to_string()
+                    .as_bytes()
+                    .to_vec(),
+            });
+            dg.transactions.get_transactions().unwrap().push(refresh);
+        }
+    }
+
+    if transactions.is_empty() {
+        return;
+    }
+
+    let mut locked_state = state.lock().unwrap();
+    let channel = locked_state.get_sip_channel().unwrap();
+    for event in transactions {
+        channel
+            .0
+            .send(MpscBase {
+                event: Some(event),
+                exit: false,
+            })
+            .unwrap();
+    }
+}
+
+/// Takes the state a NOTIFY reports, solicited or not, a notifier may
+/// send the first NOTIFY before the response to our SUBSCRIBE
+pub fn subscription_notified(request: &Request, state: &mut State) {
+    let event = match get_other_header(&request.headers, "Event") {
+        Some(event) => event
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+        None => return,
+    };
+    let terminated = get_other_header(&request.headers, "Subscription-State")
+        .map_or(false, |subscription_state| {
+            subscription_state.trim().starts_with("terminated")
+        });
+
+    {
+        let mut subscriptions = state.get_subscriptions().unwrap();
+        if let Some(subscription) = subscriptions.iter_mut().find(|subscription| {
+            subscription.event == event && subscription.dialog.matches_request(request)
+        }) {
+            let dg = &mut subscription.dialog;
+            if dg.remote_tag.is_none() {
+                dg.remote_tag = request
+                    .from_header()
+                    .ok()
+                    .and_then(|from| from.typed().ok())
+                    .and_then(|from| get_tag(&from.params));
+            }
+            if terminated {
+                info!("subscription {} terminated by the notifier", dg.call_id);
+                dg.state = DialogState::Terminated;
+                dg.refresh_at = None;
+            }
+            if event == "dialog" {
+                if let Some(line_state) = get_line_state(&request.body) {
+                    info!(
+                        "{} is {:?}",
+                        dg.remote_uri.as_deref().unwrap_or_default(),
+                        line_state
+                    );
+                    subscription.line_state = Some(line_state);
+                }
+            }
+        }
+    }
+
+    if event == "message-summary" {
+        if let Some(summary) = get_message_summary(&request.body) {
+            info!(
+                "messages waiting: {}, {} new, {} old",
+                summary.waiting, summary.new, summary.old
+            );
+            *state.get_mwi().unwrap() = Some(summary);
+        }
+    }
+}
+
+/// Last reported state of every extension monitored with the dialog event package,
+/// `None` until the first NOTIFY
+pub fn get_line_states(state: &mut State) -> BTreeMap<String, Option<LineState>> {
+    state
+        .get_subscriptions()
+        .unwrap()
+        .iter()
+        .filter(|subscription| subscription.event == "dialog")
+        .map(|subscription| {
+            let uri = subscription.dialog.remote_uri.clone().unwrap_or_default();
+            (
+                get_user_from_uri(&uri).unwrap_or(uri),
+                subscription.line_state,
+            )
+        })
+        .collect()
+}
@@ -10,6 +10,8 @@ mod config;
 mod flow;
 /// Gets IP information
 mod network;
+/// Parses bodies and header values of received messages
+mod parsers;
 /// Logging
 mod slog;
 /// Actions that need to happen when the UA starts
@@ -36,18 +38,20 @@ mod util;
 
 ///PCAP
 // mod pcap;
+use config::AnswerPolicy;
 use menu::menu_commands::send_menu_commands;
-use network::get_ipv4;
+use network::get_interface;
 use processor::message::{setup_processor, Message, MessageType};
 use rocket::fairing::AdHoc;
+use rocket::http::ContentType;
 use rocket::response::status;
 use rocket::State;
-use state::dialogs::{Direction, State as SipState, UdpCommand};
+use state::dialogs::{State as SipState, UdpCommand};
 use state::options::{SelfConfiguration, Verbosity};
 use std::sync::mpsc::{sync_channel, SyncSender};
 use std::sync::{Arc, Mutex};
 use std::{thread, time::Duration};
-// use uuid::Uuid;
+use uuid::Uuid;
 
 // use crate::pcap::capture;
 use crate::startup::registration::unregister_ua;
@@ -60,17 +64,265 @@ extern crate rocket;
 
 #[post("/call/<number>")]
 fn make_call(tr: &State<SyncSender<Message>>, number: &str) -> status::Accepted<String> {
-    info!("sending dial command with {}", number);
+    // the call-id is handed out here, so the call can be hung up, held or transferred later on
+    let call_id = Uuid::new_v4().to_string();
+    info!(
+        "sending dial command with {} and call-id {}",
+        number, call_id
+    );
+    let receipt = tr.try_send(
+        Message::new(MessageType::MenuCommand, 'd', Some(number.to_string()))
+            .with_extra(call_id.clone()),
+    );
+    match receipt {
+        Ok(_) => info!("command send"),
+        Err(err) => error!("{:?}", err),
+    };
+    status::Accepted(Some(format!(
+        "number: '{}', call-id: '{}'",
+        number, call_id
+    )))
+}
+
+#[delete("/call/<call_id>")]
+fn hang_up(tr: &State<SyncSender<Message>>, call_id: &str) -> status::Accepted<String> {
+    info!("sending hang up command for {}", call_id);
+    let receipt = tr.try_send(Message::new(
+        MessageType::MenuCommand,
+        'h',
+        Some(call_id.to_string()),
+    ));
+    match receipt {
+        Ok(_) => info!("command send"),
+        Err(err) => error!("{:?}", err),
+    };
+    status::Accepted(Some(format!("call-id: '{}'", call_id)))
+}
+
+#[post("/call/<call_id>/answer")]
+fn answer_call(tr: &State<SyncSender<Message>>, call_id: &str) -> status::Accepted<String> {
+    info!("sending answer command for {}", call_id);
+    let receipt = tr.try_send(Message::new(
+        MessageType::MenuCommand,
+        'a',
+        Some(call_id.to_string()),
+    ));
+    match receipt {
+        Ok(_) => info!("command send"),
+        Err(err) => error!("{:?}", err),
+    };
+    status::Accepted(Some(format!("call-id: '{}'", call_id)))
+}
+
+#[post("/call/<call_id>/reject?<code>")]
+fn reject_call(
+    tr: &State<SyncSender<Message>>,
+    call_id: &str,
+    code: Option<u16>,
+) -> status::Accepted<String> {
+    let code = code.unwrap_or(486);
+    info!("sending reject command for {} with {}", call_id, code);
+    let receipt = tr.try_send(
+        Message::new(MessageType::MenuCommand, 'r', Some(call_id.to_string()))
+            .with_extra(code.to_string()),
+    );
+    match receipt {
+        Ok(_) => info!("command send"),
+        Err(err) => error!("{:?}", err),
+    };
+    status::Accepted(Some(format!("call-id: '{}', code: {}", call_id, code)))
+}
+
+#[post("/call/<call_id>/hold")]
+fn hold_call(tr: &State<SyncSender<Message>>, call_id: &str) -> status::Accepted<String> {
+    info!("sending hold command for {}", call_id);
+    let receipt = tr.try_send(Message::new(
+        MessageType::MenuCommand,
+        'o',
+        Some(call_id.to_string()),
+    ));
+    match receipt {
+        Ok(_) => info!("command send"),
+        Err(err) => error!("{:?}", err),
+    };
+    status::Accepted(Some(format!("call-id: '{}'", call_id)))
+}
+
+#[post("/call/<call_id>/resume")]
+fn resume_call(tr: &State<SyncSender<Message>>, call_id: &str) -> status::Accepted<String> {
+    info!("sending resume command for {}", call_id);
+    let receipt = tr.try_send(Message::new(
+        MessageType::MenuCommand,
+        'e',
+        Some(call_id.to_string()),
+    ));
+    match receipt {
+        Ok(_) => info!("command send"),
+        Err(err) => error!("{:?}", err),
+    };
+    status::Accepted(Some(format!("call-id: '{}'", call_id)))
+}
+
+#[post("/call/<call_id>/update")]
+fn update_call(tr: &State<SyncSender<Message>>, call_id: &str) -> status::Accepted<String> {
+    info!("sending update command for {}", call_id);
     let receipt = tr.try_send(Message::new(
         MessageType::MenuCommand,
-        'd',
-        Some(number.to_string()),
+        'n',
+        Some(call_id.to_string()),
     ));
     match receipt {
         Ok(_) => info!("command send"),
         Err(err) => error!("{:?}", err),
     };
-    status::Accepted(Some(format!("number: '{}'", number)))
+    status::Accepted(Some(format!("call-id: '{}'", call_id)))
+}
+
+#[post("/call/<call_id>/dtmf/<digits>")]
+fn send_dtmf(
+    tr: &State<SyncSender<Message>>,
+    call_id: &str,
+    digits: &str,
+) -> status::Accepted<String> {
+    info!("sending dtmf command for {} with {}", call_id, digits);
+    let receipt = tr.try_send(
+        Message::new(MessageType::MenuCommand, 'f', Some(call_id.to_string()))
+            .with_extra(digits.to_string()),
+    );
+    match receipt {
+        Ok(_) => info!("command send"),
+        Err(err) => error!("{:?}", err),
+    };
+    status::Accepted(Some(format!(
+        "call-id: '{}', digits: '{}'",
+        call_id, digits
+    )))
+}
+
+#[get("/calls")]
+fn calls(sip_state: &State<Arc<Mutex<SipState>>>) -> (ContentType, String) {
+    let calls = sip_state.lock().unwrap().get_calls();
+    (ContentType::JSON, serde_json::to_string(&calls).unwrap())
+}
+
+#[get("/call/<call_id>/dtmf")]
+fn received_dtmf(
+    sip_state: &State<Arc<Mutex<SipState>>>,
+    call_id: &str,
+) -> Option<(ContentType, String)> {
+    let digits = sip_state.lock().unwrap().get_digits(call_id)?;
+    Some((ContentType::JSON, serde_json::to_string(&digits).unwrap()))
+}
+
+#[post("/call/<call_id>/transfer/<target>")]
+fn transfer_call(
+    tr: &State<SyncSender<Message>>,
+    call_id: &str,
+    target: &str,
+) -> status::Accepted<String> {
+    info!("sending transfer command for {} to {}", call_id, target);
+    let receipt = tr.try_send(
+        Message::new(MessageType::MenuCommand, 't', Some(call_id.to_string()))
+            .with_extra(target.to_string()),
+    );
+    match receipt {
+        Ok(_) => info!("command send"),
+        Err(err) => error!("{:?}", err),
+    };
+    status::Accepted(Some(format!(
+        "call-id: '{}', target: '{}'",
+        call_id, target
+    )))
+}
+
+#[post("/call/<call_id>/transfer/<target>/attended")]
+fn attended_transfer_call(
+    tr: &State<SyncSender<Message>>,
+    call_id: &str,
+    target: &str,
+) -> status::Accepted<String> {
+    info!(
+        "sending attended transfer command for {} to {}",
+        call_id, target
+    );
+    let receipt = tr.try_send(
+        Message::new(MessageType::MenuCommand, 'c', Some(call_id.to_string()))
+            .with_extra(target.to_string()),
+    );
+    match receipt {
+        Ok(_) => info!("command send"),
+        Err(err) => error!("{:?}", err),
+    };
+    status::Accepted(Some(format!(
+        "call-id: '{}', target: '{}'",
+        call_id, target
+    )))
+}
+
+#[post("/message/<uri>", data = "<text>")]
+fn send_message(
+    tr: &State<SyncSender<Message>>,
+    uri: &str,
+    text: String,
+) -> status::Accepted<String> {
+    info!("sending message command to {}", uri);
+    let receipt = tr.try_send(
+        Message::new(MessageType::MenuCommand, 'm', Some(uri.to_string())).with_extra(text),
+    );
+    match receipt {
+        Ok(_) => info!("command send"),
+        Err(err) => error!("{:?}", err),
+    };
+    status::Accepted(Some(format!("uri: '{}'", uri)))
+}
+
+#[get("/messages")]
+fn inbox(sip_state: &State<Arc<Mutex<SipState>>>) -> (ContentType, String) {
+    let mut locked_state = sip_state.lock().unwrap();
+    let messages = locked_state.get_inbox().unwrap();
+    (
+        ContentType::JSON,
+        serde_json::to_string(&*messages).unwrap(),
+    )
+}
+
+#[get("/mwi")]
+fn message_waiting(sip_state: &State<Arc<Mutex<SipState>>>) -> Option<(ContentType, String)> {
+    let mut locked_state = sip_state.lock().unwrap();
+    let summary = locked_state.get_mwi().unwrap().clone()?;
+    Some((ContentType::JSON, serde_json::to_string(&summary).unwrap()))
+}
+
+#[get("/blf")]
+fn busy_lamps(sip_state: &State<Arc<Mutex<SipState>>>) -> (ContentType, String) {
+    let states = flow::subscription::get_line_states(&mut sip_state.lock().unwrap());
+    (ContentType::JSON, serde_json::to_string(&states).unwrap())
+}
+
+#[post("/answer/<mode>?<ring>&<code>&<percentage>")]
+fn answer_policy(
+    tr: &State<SyncSender<Message>>,
+    mode: &str,
+    ring: Option<u64>,
+    code: Option<u16>,
+    percentage: Option<u8>,
+) -> Result<status::Accepted<String>, status::BadRequest<String>> {
+    let policy = match AnswerPolicy::from_parts(mode, ring, code, percentage) {
+        Some(policy) => policy,
+        None => return Err(status::BadRequest(Some(format!("mode: '{}'", mode)))),
+    };
+
+    info!("sending answer policy command with {:?}", policy);
+    let receipt = tr.try_send(Message::new(
+        MessageType::MenuCommand,
+        'p',
+        Some(serde_json::to_string(&policy).unwrap()),
+    ));
+    match receipt {
+        Ok(_) => info!("command send"),
+        Err(err) => error!("{:?}", err),
+    };
+    Ok(status::Accepted(Some(format!("policy: '{:?}'", policy))))
 }
 
 #[post("/log")]
@@ -84,13 +336,14 @@ fn toggle_log(tr: &State<SyncSender<Message>>) -> status::Accepted<String> {
 fn rocket() -> _ {
     let conf = config::read("./config.json").unwrap();
 
-    let interface = match get_ipv4() {
-        Ok(ipv4) => ipv4,
-        Err(why) => panic!("{}", why),
+    let ip = match conf.local_ip {
+        Some(ip) => ip,
+        None => match get_interface(&conf.sip_server, conf.sip_port, conf.interface.as_deref()) {
+            Ok(interface) => interface.addr.ip(),
+            Err(why) => panic!("{}", why),
+        },
     };
 
-    let ip = interface.addr.ip();
-
     // PCAP
     // let pcap_conf = conf.clone();
     // tokio::spawn(async move {
@@ -111,13 +364,37 @@ fn rocket() -> _ {
     let sip_state = dialog_state.clone();
     let http_state = dialog_state.clone();
     let publisher_state = dialog_state.clone();
+    let api_state = dialog_state.clone();
 
     // Needed to unregister the UA on shutdown
     let exit_config = conf.clone();
 
     let rocket = rocket::build()
         .manage(mtx)
-        .mount("/", routes![make_call, toggle_log])
+        .manage(api_state)
+        .mount(
+            "/",
+            routes![
+                make_call,
+                calls,
+                hang_up,
+                answer_call,
+                reject_call,
+                hold_call,
+                resume_call,
+                update_call,
+                send_dtmf,
+                received_dtmf,
+                transfer_call,
+                attended_transfer_call,
+                send_message,
+                inbox,
+                message_waiting,
+                busy_lamps,
+                answer_policy,
+                toggle_log
+            ],
+        )
         .attach(AdHoc::on_shutdown("Shutdown Printer", |_| {
             Box::pin(async move {
                 info!("sending unregister command");
@@ -128,8 +405,8 @@ fn rocket() -> _ {
         }));
 
     let local_conf = SelfConfiguration {
-        flow: Direction::Inbound,
         verbosity: Verbosity::Extreme,
+        answer: conf.answer.clone(),
         ip,
     };
 
@@ -138,6 +415,14 @@ fn rocket() -> _ {
     sip::register_event_loop::reg_event_loop(&conf, reg_state, ip);
     sip::sip_event_loop::sip_event_loop(&conf, sip_state, &arc_settings);
 
+    if let Some(mailbox) = &conf.mwi {
+        flow::subscription::subscribe(&conf, &ip, "message-summary", mailbox, dialog_state.clone());
+    }
+
+    for extension in &conf.blf {
+        flow::subscription::subscribe(&conf, &ip, "dialog", extension, dialog_state.clone());
+    }
+
     tokio::spawn(async move {
         'thread: loop {
             let command_state = dialog_state.clone();
@@ -3,15 +3,19 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use log::info;
+use log::{error, info};
 
 use crate::{
     config::JSONConfiguration,
-    flow::outbound::{outbound_configure, outbound_start},
+    flow::{
+        call::{answer, attended_transfer, dtmf, hang_up, hold, reject_call, transfer, update},
+        message::send_message,
+        outbound::{outbound_configure, outbound_start},
+    },
     processor::message::Message,
     startup::registration::unregister_ua,
     state::{
-        dialogs::{Direction, State},
+        dialogs::State,
         options::{SelfConfiguration, Verbosity},
     },
 };
@@ -49,16 +53,110 @@ pub fn send_menu_commands(
                     info!("checking dial command");
 
                     if is_string_numeric(o.clone()) {
-                        settings.flow = Direction::Outbound;
-                        outbound_configure(conf, ip, o, dialog_state.clone());
-                        outbound_start(conf, dialog_state, &settings.verbosity);
+                        let call_id = outbound_configure(
+                            conf,
+                            ip,
+                            o,
+                            processable_object.extra.clone(),
+                            dialog_state.clone(),
+                        );
+                        info!("dialing {} with call-id {}", o, call_id);
+                        outbound_start(conf, &call_id, dialog_state, &settings.verbosity);
                     }
                 }
                 None => todo!(),
             };
             false
         }
-        'a' => todo!(),
+        'h' => {
+            match &processable_object.content {
+                Some(call_id) => hang_up(call_id, dialog_state),
+                None => usage('h', "a call-id"),
+            };
+            false
+        }
+        'o' => {
+            match &processable_object.content {
+                Some(call_id) => hold(call_id, true, dialog_state),
+                None => usage('o', "a call-id"),
+            };
+            false
+        }
+        'e' => {
+            match &processable_object.content {
+                Some(call_id) => hold(call_id, false, dialog_state),
+                None => usage('e', "a call-id"),
+            };
+            false
+        }
+        'n' => {
+            match &processable_object.content {
+                Some(call_id) => update(call_id, dialog_state),
+                None => usage('n', "a call-id"),
+            };
+            false
+        }
+        'm' => {
+            match (&processable_object.content, &processable_object.extra) {
+                (Some(target), Some(text)) => send_message(conf, ip, target, text, dialog_state),
+                _ => usage('m', "a target and the text"),
+            };
+            false
+        }
+        'f' => {
+            match (&processable_object.content, &processable_object.extra) {
+                (Some(call_id), Some(digits)) => dtmf(call_id, digits, dialog_state),
+                _ => usage('f', "a call-id and the digits"),
+            };
+            false
+        }
+        't' => {
+            match (&processable_object.content, &processable_object.extra) {
+                (Some(call_id), Some(target)) => transfer(call_id, target, conf, dialog_state),
+                _ => usage('t', "a call-id and a transfer target"),
+            };
+            false
+        }
+        'c' => {
+            match (&processable_object.content, &processable_object.extra) {
+                (Some(call_id), Some(target)) => {
+                    attended_transfer(call_id, target, conf, ip, dialog_state, &settings.verbosity)
+                }
+                _ => usage('c', "a call-id and a transfer target"),
+            };
+            false
+        }
+        'p' => {
+            match &processable_object.content {
+                Some(policy) => match serde_json::from_str(policy) {
+                    Ok(policy) => settings.answer = policy,
+                    Err(err) => error!("invalid answer policy {:?}", err),
+                },
+                None => usage('p', "an answer policy"),
+            };
+            false
+        }
+        'a' => {
+            match &processable_object.content {
+                Some(call_id) => answer(call_id, dialog_state),
+                None => usage('a', "a call-id"),
+            };
+            false
+        }
+        'r' => {
+            match &processable_object.content {
+                Some(call_id) => {
+                    let code = processable_object
+                        .extra
+                        .as_ref()
+                        .and_then(|code| code.parse::<u16>().ok())
+                        .unwrap_or(486);
+                    reject_call(call_id, code.into(), dialog_state);
+                }
+                None => usage('r', "a call-id"),
+            };
+            false
+        }
         _ => {
             info!(
                 "{:?}: Invalid Command/Not supported",
@@ -69,6 +167,11 @@ pub fn send_menu_commands(
     }
 }
 
+/// A command that came without the arguments it needs
+fn usage(bind: char, arguments: &str) {
+    error!("{:?}: needs {}", bind, arguments);
+}
+
 fn is_string_numeric(str: String) -> bool {
     for c in str.chars() {
         if !c.is_numeric() {
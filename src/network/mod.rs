@@ -1,11 +1,18 @@
+use crate::commands::helper::get_host;
 use if_addrs::Interface;
+use std::net::{IpAddr, ToSocketAddrs};
+
+/// Whether the interface is the one asked for, any interface will do without a name
+fn is_named(interface: &Interface, name: Option<&str>) -> bool {
+    name.map_or(true, |name| interface.name == name)
+}
 
 /// Iterates through all the available interfaces and pick the first IPV4
-pub fn get_ipv4() -> Result<Interface, String> {
+pub fn get_ipv4(name: Option<&str>) -> Result<Interface, String> {
     let is_there_an_ipv4 = if_addrs::get_if_addrs()
         .unwrap()
         .into_iter()
-        .find(|ip| ip.ip().is_ipv4());
+        .find(|ip| ip.ip().is_ipv4() && is_named(ip, name));
 
     let interface = match is_there_an_ipv4 {
         Some(ipv4) => ipv4,
@@ -14,3 +21,69 @@ pub fn get_ipv4() -> Result<Interface, String> {
 
     Ok(interface)
 }
+
+/// Iterates through all the available interfaces and pick the first routable IPV6,
+/// loopback and link-local addresses are skipped, the latter need a scope to be reached
+pub fn get_ipv6(name: Option<&str>) -> Result<Interface, String> {
+    let is_there_an_ipv6 = if_addrs::get_if_addrs()
+        .unwrap()
+        .into_iter()
+        .filter(|interface| is_named(interface, name))
+        .find(|interface| match interface.ip() {
+            IpAddr::V6(ip) => !ip.is_loopback() && (ip.segments()[0] & 0xffc0) != 0xfe80,
+            IpAddr::V4(_) => false,
+        });
+
+    match is_there_an_ipv6 {
+        Some(ipv6) => Ok(ipv6),
+        None => Err("No IP V6 found".to_string()),
+    }
+}
+
+/// Interface of the address family the SIP server is reached with,
+/// IPv6 for a server that resolves to IPv6 addresses only, IPv4 otherwise,
+/// only the interface `name` is looked at, if given
+pub fn get_interface(server: &str, port: u16, name: Option<&str>) -> Result<Interface, String> {
+    let addresses: Vec<IpAddr> = (server.trim_matches(|c| c == '[' || c == ']'), port)
+        .to_socket_addrs()
+        .map(|addresses| addresses.map(|address| address.ip()).collect())
+        .unwrap_or_default();
+
+    match !addresses.is_empty() && addresses.iter().all(|address| address.is_ipv6()) {
+        true => get_ipv6(name),
+        false => get_ipv4(name),
+    }
+}
+
+/// Wildcard address of the family of `ip`, for sockets listening on any interface
+pub fn get_unspecified(ip: &IpAddr) -> &'static str {
+    match ip {
+        IpAddr::V4(_) => "0.0.0.0",
+        IpAddr::V6(_) => "[::]",
+    }
+}
+
+/// Socket address of a host, or of the host of a `user@host`, IPv6 within brackets
+pub fn get_socket_address(host: &str, port: u16) -> String {
+    let host = host.rsplit('@').next().unwrap_or(host);
+
+    format!("{}:{}", get_host(host), port)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::get_socket_address;
+
+    #[test]
+    fn socket_address() {
+        assert_eq!(get_socket_address("10.0.0.1", 5060), "10.0.0.1:5060");
+        assert_eq!(
+            get_socket_address("1001@2001:db8::1", 5060),
+            "[2001:db8::1]:5060"
+        );
+        assert_eq!(
+            get_socket_address("[2001:db8::1]", 5061),
+            "[2001:db8::1]:5061"
+        );
+    }
+}
@@ -0,0 +1,456 @@
+use crate::commands::helper::{get_other_header, MIN_SE};
+use crate::rtp::dtmf::{get_digit, get_event};
+use crate::state::dialogs::{LineState, MessageSummary};
+
+/// Duration of an INFO digit that does not state one, in milliseconds
+const INFO_DURATION: u16 = 250;
+
+/// Status code of a message/sipfrag body, e.g. `SIP/2.0 180 Ringing`
+pub fn get_sipfrag_status(body: &[u8]) -> Option<u16> {
+    String::from_utf8_lossy(body)
+        .lines()
+        .next()?
+        .split_whitespace()
+        .nth(1)?
+        .parse::<u16>()
+        .ok()
+}
+
+/// Digit and duration in milliseconds of an INFO body, either `application/dtmf-relay`
+/// (`Signal=5` and `Duration=160` lines) or `application/dtmf` (just the digit)
+pub fn get_info_digit(content_type: &str, body: &[u8]) -> Option<(char, u16)> {
+    let body = String::from_utf8_lossy(body);
+    match content_type {
+        "application/dtmf-relay" => {
+            let mut digit: Option<char> = None;
+            let mut duration = INFO_DURATION;
+            for line in body.lines() {
+                if let Some((key, value)) = line.split_once('=') {
+                    match key.trim().to_lowercase().as_str() {
+                        "signal" => digit = get_signal(value.trim()),
+                        "duration" => duration = value.trim().parse().unwrap_or(INFO_DURATION),
+                        _ => {}
+                    }
+                }
+            }
+            Some((digit?, duration))
+        }
+        "application/dtmf" => get_signal(body.trim()).map(|digit| (digit, INFO_DURATION)),
+        _ => None,
+    }
+}
+
+/// Digits come as characters, some devices send the event code instead
+fn get_signal(value: &str) -> Option<char> {
+    value
+        .parse::<u8>()
+        .ok()
+        .and_then(get_digit)
+        .or_else(|| {
+            value
+                .chars()
+                .next()
+                .filter(|digit| get_event(*digit).is_some())
+        })
+        .map(|digit| digit.to_ascii_uppercase())
+}
+
+/// Message-waiting status of a message-summary body (RFC 3842), the counts come as
+/// `Voice-Message: 2/8 (0/2)`, new and old messages followed by the urgent ones
+pub fn get_message_summary(body: &[u8]) -> Option<MessageSummary> {
+    let mut summary: Option<MessageSummary> = None;
+    let mut counts: Vec<u32> = vec![];
+    let mut account: Option<String> = None;
+
+    for line in String::from_utf8_lossy(body).lines() {
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+
+        match key.as_str() {
+            "messages-waiting" => {
+                summary = Some(MessageSummary {
+                    waiting: value.eq_ignore_ascii_case("yes"),
+                    ..Default::default()
+                })
+            }
+            "message-account" => account = Some(value.to_string()),
+            "voice-message" => {
+                counts = value
+                    .split(|c: char| !c.is_ascii_digit())
+                    .filter_map(|count| count.parse().ok())
+                    .collect()
+            }
+            _ => {}
+        }
+    }
+
+    let count = |i: usize| counts.get(i).copied().unwrap_or(0);
+    summary.map(|summary| MessageSummary {
+        account,
+        new: count(0),
+        old: count(1),
+        urgent_new: count(2),
+        urgent_old: count(3),
+        ..summary
+    })
+}
+
+/// State of the extension an `application/dialog-info+xml` body (RFC 4235) describes,
+/// any confirmed dialog makes it confirmed, any other live dialog ringing
+pub fn get_line_state(body: &[u8]) -> Option<LineState> {
+    let body = String::from_utf8_lossy(body);
+    if !body.contains("<dialog-info") {
+        return None;
+    }
+
+    let states: Vec<String> = body
+        .split("<state")
+        .skip(1)
+        .filter_map(|element| {
+            let (_, content) = element.split_once('>')?;
+            Some(content.split('<').next()?.trim().to_lowercase())
+        })
+        .collect();
+
+    Some(if states.iter().any(|state| state == "confirmed") {
+        LineState::Confirmed
+    } else if states.iter().any(|state| state != "terminated") {
+        LineState::Ringing
+    } else {
+        LineState::Idle
+    })
+}
+
+/// Whether an option tag, e.g. `100rel`, is listed in the `name` header (Supported, Require)
+pub fn has_option_tag(headers: &rsip::Headers, name: &str, tag: &str) -> bool {
+    get_other_header(headers, name).map_or(false, |value| {
+        value
+            .split(',')
+            .any(|option| option.trim().eq_ignore_ascii_case(tag))
+    })
+}
+
+/// Interval and refresher of a Session-Expires header (RFC 4028), e.g. `1800;refresher=uac`
+pub fn parse_session_expires(value: &str) -> Option<(u32, Option<String>)> {
+    let mut parts = value.split(';').map(|part| part.trim());
+    let interval = parts.next()?.parse().ok()?;
+    let refresher = parts.find_map(|part| {
+        let (key, value) = part.split_once('=')?;
+        match key.trim().eq_ignore_ascii_case("refresher") {
+            true => Some(value.trim().to_lowercase()),
+            false => None,
+        }
+    });
+
+    Some((interval, refresher))
+}
+
+/// Min-SE of a request or a 422 (RFC 4028), the shortest session interval its sender accepts
+pub fn get_min_se(headers: &rsip::Headers) -> Option<u32> {
+    get_other_header(headers, "Min-SE")?
+        .split(';')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Whether a request asks for a session interval below our Min-SE,
+/// it gets a 422 instead of an answer
+pub fn session_interval_too_small(headers: &rsip::Headers) -> bool {
+    get_other_header(headers, "Session-Expires")
+        .and_then(|session_expires| parse_session_expires(&session_expires))
+        .map_or(false, |(interval, _)| interval < MIN_SE)
+}
+
+/// Session interval and refresher our 2xx agrees to for a request with these headers,
+/// none when the request asks for no session timer, the interval is never below
+/// the Min-SE of the request
+pub fn negotiate_session_timer(headers: &rsip::Headers) -> Option<(u32, String)> {
+    let (interval, refresher) =
+        parse_session_expires(&get_other_header(headers, "Session-Expires")?)?;
+    let refresher = match refresher.as_deref() {
+        Some("uac") | Some("uas") => refresher?,
+        // only a caller supporting timers can refresh on its own
+        _ if has_option_tag(headers, "Supported", "timer") => "uac".to_string(),
+        _ => "uas".to_string(),
+    };
+
+    let min_se = get_min_se(headers).unwrap_or(0);
+    Some((std::cmp::max(interval, min_se), refresher))
+}
+
+/// RSeq and CSeq number of a RAck header (RFC 3262), e.g. `776656 1 INVITE`
+pub fn parse_rack(value: &str) -> Option<(u32, u32)> {
+    let mut parts = value.split_whitespace();
+    let rseq = parts.next()?.parse().ok()?;
+    let cseq = parts.next()?.parse().ok()?;
+
+    Some((rseq, cseq))
+}
+
+/// Call-ID, to-tag and from-tag of a Replaces header (RFC 3891),
+/// e.g. `425928@bobster.example.org;to-tag=7743;from-tag=6472`
+pub fn parse_replaces(value: &str) -> Option<(String, String, String)> {
+    let mut parts = value.split(';').map(|part| part.trim());
+    let call_id = parts.next().filter(|call_id| !call_id.is_empty())?;
+
+    let mut to_tag: Option<&str> = None;
+    let mut from_tag: Option<&str> = None;
+    for part in parts {
+        match part.split_once('=') {
+            Some((key, tag)) if key.eq_ignore_ascii_case("to-tag") => to_tag = Some(tag),
+            Some((key, tag)) if key.eq_ignore_ascii_case("from-tag") => from_tag = Some(tag),
+            _ => {}
+        }
+    }
+
+    Some((
+        call_id.to_string(),
+        to_tag?.to_string(),
+        from_tag?.to_string(),
+    ))
+}
+
+/// Replaces header a uri carries (RFC 3891), the Refer-To of an attended transfer
+/// carries the one of the call the referred call takes over
+pub fn get_uri_replaces(uri: &str) -> Option<String> {
+    let uri = uri.trim().trim_start_matches('<');
+    let uri = uri.split('>').next()?;
+    let (_, headers) = uri.split_once('?')?;
+
+    headers
+        .split('&')
+        .find_map(|header| match header.split_once('=') {
+            Some((name, value)) if name.eq_ignore_ascii_case("Replaces") => {
+                Some(get_unescaped(value))
+            }
+            _ => None,
+        })
+}
+
+/// Undoes the %-escaping of a uri header value, `%40` is `@` again
+fn get_unescaped(value: &str) -> String {
+    let mut bytes = vec![];
+    let mut rest = value.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (byte, escaped) {
+            (b'%', Some(escaped)) => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+/// Uri of a Refer-To out of its angle brackets, along with the headers it carries
+pub fn get_refer_to(value: &str) -> Option<String> {
+    let uri = value.trim().trim_start_matches('<');
+    let uri = uri.split('>').next()?.trim();
+
+    match uri.starts_with("sip:") || uri.starts_with("sips:") {
+        true => Some(uri.to_string()),
+        false => None,
+    }
+}
+
+/// User part of a uri, in or out of angle brackets, e.g. `<sip:1005@sip.server.com>`
+pub fn get_user_from_uri(value: &str) -> Option<String> {
+    let uri = value.trim().trim_start_matches('<');
+    let uri = uri.split('>').next()?;
+    let uri = uri
+        .strip_prefix("sips:")
+        .or_else(|| uri.strip_prefix("sip:"))
+        .unwrap_or(uri);
+    let (user, _) = uri.split_once('@')?;
+
+    match user.split(';').next() {
+        Some(user) if !user.is_empty() => Some(user.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::{
+        helper::{get_refer_to_with_replaces, MIN_SE},
+        reject::reject,
+    };
+    use crate::parsers::{
+        get_info_digit, get_line_state, get_message_summary, get_min_se, get_refer_to,
+        get_sipfrag_status, get_uri_replaces, get_user_from_uri, negotiate_session_timer,
+        parse_rack, parse_replaces, parse_session_expires, session_interval_too_small,
+    };
+    use crate::state::dialogs::LineState;
+    use rsip::{SipMessage, StatusCode};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn sipfrag_status_line() {
+        assert_eq!(get_sipfrag_status(b"SIP/2.0 200 OK\r\n"), Some(200));
+        assert_eq!(get_sipfrag_status(b"SIP/2.0 486 Busy Here"), Some(486));
+        assert_eq!(get_sipfrag_status(b""), None);
+    }
+
+    #[test]
+    fn info_digits() {
+        assert_eq!(
+            get_info_digit("application/dtmf-relay", b"Signal=5\r\nDuration=160\r\n"),
+            Some(('5', 160))
+        );
+        assert_eq!(
+            get_info_digit("application/dtmf-relay", b"Signal=10\r\n"),
+            Some(('*', 250))
+        );
+        assert_eq!(get_info_digit("application/dtmf", b"#"), Some(('#', 250)));
+        assert_eq!(get_info_digit("text/plain", b"1"), None);
+    }
+
+    #[test]
+    fn message_summary() {
+        let body = b"Messages-Waiting: yes\r\nMessage-Account: sip:1004@vm.server.com\r\nVoice-Message: 2/8 (1/0)\r\n";
+        let summary = get_message_summary(body).unwrap();
+
+        assert!(summary.waiting);
+        assert_eq!(summary.account.as_deref(), Some("sip:1004@vm.server.com"));
+        assert_eq!((summary.new, summary.old), (2, 8));
+        assert_eq!((summary.urgent_new, summary.urgent_old), (1, 0));
+        assert!(get_message_summary(b"Voice-Message: 2/8\r\n").is_none());
+    }
+
+    #[test]
+    fn line_state() {
+        let dialog_info = |state: &str| {
+            format!(
+                "<?xml version=\"1.0\"?>\r\n<dialog-info xmlns=\"urn:ietf:params:xml:ns:dialog-info\" version=\"1\" state=\"full\" entity=\"sip:1001@sip.server.com\">\r\n{}</dialog-info>\r\n",
+                state
+            )
+        };
+        let dialog = |state: &str| {
+            format!(
+                "<dialog id=\"a1\" direction=\"recipient\"><state>{}</state></dialog>\r\n",
+                state
+            )
+        };
+
+        assert_eq!(
+            get_line_state(dialog_info("").as_bytes()),
+            Some(LineState::Idle)
+        );
+        assert_eq!(
+            get_line_state(dialog_info(&dialog("early")).as_bytes()),
+            Some(LineState::Ringing)
+        );
+        assert_eq!(
+            get_line_state(dialog_info(&(dialog("terminated") + &dialog("confirmed"))).as_bytes()),
+            Some(LineState::Confirmed)
+        );
+        assert_eq!(get_line_state(b"Messages-Waiting: no"), None);
+    }
+
+    #[test]
+    fn session_timer() {
+        assert_eq!(
+            parse_session_expires("1800;refresher=UAC"),
+            Some((1800, Some("uac".to_string())))
+        );
+        assert_eq!(parse_session_expires("90"), Some((90, None)));
+        assert_eq!(parse_session_expires("refresher=uas"), None);
+
+        let mut headers: rsip::Headers = Default::default();
+        headers.push(rsip::Header::Other("Session-Expires".into(), "1800".into()));
+        assert!(!session_interval_too_small(&headers));
+        assert_eq!(
+            negotiate_session_timer(&headers),
+            Some((1800, "uas".to_string()))
+        );
+
+        headers.push(rsip::Header::Other(
+            "Supported".into(),
+            "100rel, timer".into(),
+        ));
+        headers.push(rsip::Header::Other("Min-SE".into(), "2400".into()));
+        assert_eq!(get_min_se(&headers), Some(2400));
+        assert_eq!(
+            negotiate_session_timer(&headers),
+            Some((2400, "uac".to_string()))
+        );
+    }
+
+    #[test]
+    fn short_session_interval_gets_422() {
+        let request = SipMessage::try_from(
+            "INVITE sip:1001@10.0.0.1 SIP/2.0\r\nVia: SIP/2.0/UDP 10.0.0.2:5060;branch=z9hG4bK1\r\nFrom: <sip:1002@10.0.0.2>;tag=a\r\nTo: <sip:1001@10.0.0.1>\r\nCall-ID: call\r\nCSeq: 1 INVITE\r\nSession-Expires: 60\r\nContent-Length: 0\r\n\r\n",
+        )
+        .unwrap();
+        let request = match request {
+            SipMessage::Request(request) => request,
+            _ => unreachable!(),
+        };
+        assert!(session_interval_too_small(&request.headers));
+
+        let response = match reject(&request, StatusCode::from(422), None) {
+            SipMessage::Response(response) => response,
+            _ => unreachable!(),
+        };
+        assert_eq!(response.status_code.code(), 422);
+        assert_eq!(get_min_se(&response.headers), Some(MIN_SE));
+    }
+
+    #[test]
+    fn rack_values() {
+        assert_eq!(parse_rack("776656 1 INVITE"), Some((776656, 1)));
+        assert_eq!(parse_rack("776656"), None);
+    }
+
+    #[test]
+    fn replaces_round_trip() {
+        let refer_to = get_refer_to_with_replaces("sip:1004@sip.server.com", "a@b", "1", "2");
+        assert_eq!(
+            refer_to,
+            "sip:1004@sip.server.com?Replaces=a%40b%3Bto-tag%3D1%3Bfrom-tag%3D2"
+        );
+        assert_eq!(
+            parse_replaces("a@b;from-tag=2;to-tag=1"),
+            Some(("a@b".to_string(), "1".to_string(), "2".to_string()))
+        );
+        assert_eq!(parse_replaces("a@b;to-tag=1"), None);
+        assert_eq!(
+            get_uri_replaces(&format!("<{}>", refer_to)),
+            Some("a@b;to-tag=1;from-tag=2".to_string())
+        );
+        assert_eq!(get_uri_replaces("<sip:1004@sip.server.com>"), None);
+    }
+
+    #[test]
+    fn user_from_refer_to() {
+        assert_eq!(
+            get_user_from_uri("<sip:1005@sip.server.com;transport=udp>"),
+            Some("1005".to_string())
+        );
+        assert_eq!(
+            get_user_from_uri("sip:1005@sip.server.com"),
+            Some("1005".to_string())
+        );
+        assert_eq!(get_user_from_uri("<sip:sip.server.com>"), None);
+        assert_eq!(
+            get_refer_to("<sips:1005@10.0.0.2;transport=tls?Replaces=a%40b>;x=y"),
+            Some("sips:1005@10.0.0.2;transport=tls?Replaces=a%40b".to_string())
+        );
+        assert_eq!(get_refer_to("<tel:+15551234>"), None);
+    }
+}
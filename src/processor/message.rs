@@ -12,6 +12,8 @@ pub struct Message {
     pub message_type: MessageType,
     pub bind: char,
     pub content: Option<String>,
+    /// Second argument, for commands that need more than `content`
+    pub extra: Option<String>,
 }
 
 pub enum MessageType {
@@ -24,6 +26,12 @@ impl Message {
             message_type,
             bind,
             content,
+            extra: None,
         }
     }
+
+    pub fn with_extra(mut self, extra: String) -> Self {
+        self.extra = Some(extra);
+        self
+    }
 }
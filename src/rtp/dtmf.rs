@@ -0,0 +1,209 @@
+use pnet_macros_support::packet::Packet;
+use rand::Rng;
+use std::{net::UdpSocket, thread, time::Duration};
+
+use crate::{
+    rtp::{MutableRtpPacket, RtpType},
+    state::options::Verbosity,
+    transmissions::sockets::{send, SocketV4},
+};
+
+/// Samples covered by one packet, 50ms at 8000Hz
+const STEP: u16 = 400;
+/// Packets sent while the digit is pressed, before the end packets
+const STEPS: u16 = 4;
+/// Silence between two digits
+const GAP: u64 = 100;
+/// Volume of the tone, in -dBm0
+const VOLUME: u8 = 10;
+
+/// Event code of a DTMF digit
+pub fn get_event(digit: char) -> Option<u8> {
+    match digit.to_ascii_uppercase() {
+        '0'..='9' => digit.to_digit(10).map(|d| d as u8),
+        '*' => Some(10),
+        '#' => Some(11),
+        d @ 'A'..='D' => Some(12 + (d as u8 - b'A')),
+        _ => None,
+    }
+}
+
+/// DTMF digit of an event code
+pub fn get_digit(event: u8) -> Option<char> {
+    match event {
+        0..=9 => Some((b'0' + event) as char),
+        10 => Some('*'),
+        11 => Some('#'),
+        12..=15 => Some((b'A' + event - 12) as char),
+        _ => None,
+    }
+}
+
+/// Event code, end bit and duration in samples of a telephone-event payload
+pub fn parse_event(payload: &[u8]) -> Option<(u8, bool, u16)> {
+    match payload {
+        [event, flags, high, low, ..] => {
+            Some((*event, flags & 0x80 != 0, u16::from_be_bytes([*high, *low])))
+        }
+        _ => None,
+    }
+}
+
+/// Packets of a single digit along with how long to wait before sending each,
+/// the first one carries the marker bit, all of them share the timestamp
+/// and the last three have the end bit set
+pub fn dtmf_packets(
+    event: u8,
+    payload_type: u8,
+    ssrc: u32,
+    sequence: &mut u16,
+    timestamp: u32,
+) -> Vec<(Duration, Vec<u8>)> {
+    let mut packets = vec![];
+    let step = Duration::from_millis(50);
+
+    for i in 1..=STEPS {
+        let wait = if i == 1 { Duration::ZERO } else { step };
+        packets.push((
+            wait,
+            event_packet(
+                event,
+                false,
+                i * STEP,
+                i == 1,
+                payload_type,
+                ssrc,
+                sequence,
+                timestamp,
+            ),
+        ));
+    }
+
+    for i in 0..3 {
+        let wait = if i == 0 { step } else { Duration::ZERO };
+        packets.push((
+            wait,
+            event_packet(
+                event,
+                true,
+                (STEPS + 1) * STEP,
+                false,
+                payload_type,
+                ssrc,
+                sequence,
+                timestamp,
+            ),
+        ));
+    }
+
+    packets
+}
+
+/// Sends `digits` to the media address of a call, one event after the other
+pub fn send_dtmf(digits: &str, payload_type: u8, destination: (String, u16)) {
+    let events: Vec<u8> = digits.chars().filter_map(get_event).collect();
+
+    thread::spawn(move || {
+        let any = match destination.0.contains(':') {
+            true => "[::]:0",
+            false => "0.0.0.0:0",
+        };
+        let mut socket = match UdpSocket::bind(any) {
+            Ok(socket) => socket,
+            Err(why) => {
+                error!("could not bind a socket for dtmf, {}", why);
+                return;
+            }
+        };
+
+        let mut rng = rand::thread_rng();
+        let ssrc: u32 = rng.gen();
+        let mut sequence: u16 = rng.gen();
+        let mut timestamp: u32 = rng.gen();
+
+        for event in events {
+            for (wait, bytes) in dtmf_packets(event, payload_type, ssrc, &mut sequence, timestamp) {
+                thread::sleep(wait);
+                send(
+                    &mut socket,
+                    &SocketV4 {
+                        ip: destination.0.clone(),
+                        port: destination.1,
+                        bytes,
+                    },
+                    &Verbosity::Quiet,
+                );
+            }
+
+            thread::sleep(Duration::from_millis(GAP));
+            timestamp = timestamp.wrapping_add(((STEPS + 1) * STEP) as u32 + GAP as u32 * 8);
+        }
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn event_packet(
+    event: u8,
+    end: bool,
+    duration: u16,
+    marker: bool,
+    payload_type: u8,
+    ssrc: u32,
+    sequence: &mut u16,
+    timestamp: u32,
+) -> Vec<u8> {
+    let mut buffer = [0_u8; 16];
+    let mut packet = MutableRtpPacket::new(&mut buffer).unwrap();
+    packet.set_version(2);
+    packet.set_marker(marker as u8);
+    packet.set_payload_type(RtpType::new(payload_type));
+    packet.set_sequence(*sequence);
+    packet.set_timestamp(timestamp);
+    packet.set_ssrc(ssrc);
+    packet.set_payload(&[
+        event,
+        ((end as u8) << 7) | VOLUME,
+        (duration >> 8) as u8,
+        duration as u8,
+    ]);
+
+    *sequence = sequence.wrapping_add(1);
+
+    packet.consume_to_immutable().packet().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rtp::dtmf::{dtmf_packets, get_digit, get_event, parse_event};
+
+    #[test]
+    fn digit_packets() {
+        assert_eq!(get_event('#'), Some(11));
+        assert_eq!(get_event('d'), Some(15));
+
+        let mut sequence = u16::MAX;
+        let packets = dtmf_packets(5, 101, 1, &mut sequence, 160);
+
+        // marker on the first packet only, payload type kept
+        assert_eq!(packets[0].1[1], 0x80 | 101);
+        assert_eq!(packets[1].1[1], 101);
+
+        // three identical end packets, apart from the sequence number
+        let ends: Vec<&Vec<u8>> = packets.iter().rev().take(3).map(|(_, p)| p).collect();
+        assert!(ends.iter().all(|p| p[12] == 5 && p[13] & 0x80 != 0));
+        assert!(ends.iter().all(|p| p[14..] == ends[0][14..]));
+        assert_eq!(sequence, packets.len() as u16 - 1);
+    }
+
+    #[test]
+    fn received_event() {
+        let mut sequence = 0;
+        let packets = dtmf_packets(11, 101, 1, &mut sequence, 160);
+        let (event, end, duration) = parse_event(&packets.last().unwrap().1[12..]).unwrap();
+
+        assert_eq!(get_digit(event), Some('#'));
+        assert!(end);
+        assert_eq!(duration, 2000);
+        assert_eq!(get_digit(16), None);
+    }
+}
@@ -2,15 +2,21 @@ use pnet_macros_support::packet::Packet;
 use rand::Rng;
 use std::{
     f64::consts::PI,
-    net::{IpAddr, UdpSocket},
+    net::{IpAddr, SocketAddr, UdpSocket},
     sync::{Arc, Mutex},
 };
 use tokio::task::JoinHandle;
 
 use crate::{
+    network::get_unspecified,
+    rtp::dtmf::{get_digit, parse_event},
     rtp::MutableRtpPacket,
+    rtp::RtpPacket,
     rtp::RtpType,
-    state::{dialogs::State, options::Verbosity},
+    state::{
+        dialogs::{Digit, State},
+        options::Verbosity,
+    },
     transmissions::sockets::{peek, receive_base, send, MpscBase, SocketV4},
 };
 use std::time::Duration;
@@ -31,15 +37,18 @@ pub fn rtp_event_loop(
     dialog_state: Arc<Mutex<State>>,
     r_connection: &IpAddr,
     rtp_port: u16,
+    call_id: &str,
 ) -> JoinHandle<()> {
     let connection = *c_connection;
     let rtp_connection = *r_connection;
+    let call_id = call_id.to_string();
 
     tokio::spawn(async move {
-        let mut socket = UdpSocket::bind(format!("0.0.0.0:{}", rtp_port)).unwrap();
+        let mut socket =
+            UdpSocket::bind(format!("{}:{}", get_unspecified(&connection), rtp_port)).unwrap();
         let _io_result = socket.set_read_timeout(Some(Duration::new(1, 0)));
         socket
-            .connect(format!("{}:{}", &connection, &port))
+            .connect(SocketAddr::new(connection, port))
             .expect("connect function failed");
 
         let mut rtp_buffer = [0_u8; 65535];
@@ -50,6 +59,8 @@ pub fn rtp_event_loop(
         let mut n2: u16 = rng.gen();
         let n3: u32 = rng.gen();
         let proper_loop = 0;
+        // the end packet of a telephone-event is sent three times
+        let mut last_event: Option<u32> = None;
 
         info!("target rtp located : {:?}:{:?}", rtp_connection, rtp_port);
         info!("source rtp located : {:?}:{}", connection, 49152);
@@ -59,6 +70,8 @@ pub fn rtp_event_loop(
             let mut send_buffer = [0_u8; 1500];
 
             let mut state = dialog_state.lock().unwrap();
+            let on_hold = state.is_on_hold(&call_id);
+            let dtmf_payload = state.get_dtmf_payload(&call_id);
             let channel = state.get_rtp_channel().unwrap();
 
             let mut packet = MutableRtpPacket::new(&mut send_buffer).unwrap();
@@ -105,17 +118,20 @@ pub fn rtp_event_loop(
 
             packet.set_payload(&body);
 
-            channel
-                .0
-                .send(MpscBase {
-                    event: Some(SocketV4 {
-                        ip: rtp_connection.to_string(),
-                        port: rtp_port,
-                        bytes: packet.consume_to_immutable().packet().to_vec(),
-                    }),
-                    exit: false,
-                })
-                .unwrap();
+            // a held call keeps receiving, but sends no audio
+            if !on_hold {
+                channel
+                    .0
+                    .send(MpscBase {
+                        event: Some(SocketV4 {
+                            ip: rtp_connection.to_string(),
+                            port: rtp_port,
+                            bytes: packet.consume_to_immutable().packet().to_vec(),
+                        }),
+                        exit: false,
+                    })
+                    .unwrap();
+            }
 
             n2 = proper_loop + 1;
 
@@ -131,9 +147,27 @@ pub fn rtp_event_loop(
             }
 
             // distribute message on the correct process
+            let mut received: Option<Digit> = None;
             if let Some(..) = maybe_msg {
                 let msg = maybe_msg.unwrap();
-                info!("{}", String::from_utf8_lossy(&msg));
+                match RtpPacket::new(&msg) {
+                    Some(rtp)
+                        if dtmf_payload.is_some()
+                            && rtp.get_payload_type()
+                                == RtpType::Dynamic(dtmf_payload.unwrap()) =>
+                    {
+                        if let Some((event, true, duration)) = parse_event(rtp.payload()) {
+                            if last_event != Some(rtp.get_timestamp()) {
+                                last_event = Some(rtp.get_timestamp());
+                                received = get_digit(event).map(|digit| Digit {
+                                    digit,
+                                    duration: duration / 8,
+                                });
+                            }
+                        }
+                    }
+                    _ => info!("{}", String::from_utf8_lossy(&msg)),
+                }
             }
 
             if let Ok(data) = channel.1.try_recv() {
@@ -142,6 +176,11 @@ pub fn rtp_event_loop(
                 }
                 send(&mut socket, &data.event.unwrap(), &Verbosity::Quiet);
             }
+            drop(channel);
+
+            if let Some(digit) = received {
+                state.add_digit(&call_id, digit);
+            }
         }
     })
 }
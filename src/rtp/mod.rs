@@ -2,13 +2,16 @@ use pnet_macros::packet;
 use pnet_macros_support::packet::PrimitiveValues;
 use pnet_macros_support::types::{u1, u16be, u2, u32be, u4, u7};
 
+/// RFC 4733 telephone-events
+pub mod dtmf;
 pub mod event_loop;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum RtpType {
-    Pcmu, // 0
-    Pcma, // 8
-    G722, // 9
+    Pcmu,        // 0
+    Pcma,        // 8
+    G722,        // 9
+    Dynamic(u7), // 96-127
     Unknown,
 }
 
@@ -18,6 +21,7 @@ impl RtpType {
             0 => Self::Pcmu,
             8 => Self::Pcma,
             9 => Self::G722,
+            96..=127 => Self::Dynamic(val),
             _ => Self::Unknown,
         }
     }
@@ -48,6 +52,7 @@ impl PrimitiveValues for RtpType {
             Self::Pcmu => (0,),
             Self::Pcma => (8,),
             Self::G722 => (9,),
+            Self::Dynamic(val) => (*val,),
             _ => panic!("unsuported value"),
         }
     }
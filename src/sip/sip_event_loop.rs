@@ -1,23 +1,25 @@
-use rsip::SipMessage;
-use std::{
-    net::UdpSocket,
-    sync::{Arc, Mutex},
-};
+use rsip::{Method, Response, SipMessage};
+use std::sync::{Arc, Mutex};
 use tokio::task::JoinHandle;
 
 use crate::{
     config::JSONConfiguration,
     flow::{
-        inbound::{process_request_inbound, process_response_inbound},
+        call::{
+            call_answered, cancel_hung_up, complete_transfers, notify_referrers, refresh_sessions,
+        },
+        inbound::{answer_pending, process_request_inbound, process_response_inbound},
+        message::message_answered,
         outbound::{process_request_outbound, process_response_outbound},
+        subscription::{refresh_subscriptions, subscribe_answered},
     },
     state::{
-        dialogs::{Direction, State},
+        dialogs::{Dialog, Direction, State},
         options::{SelfConfiguration, Verbosity},
+        transactions::Transaction,
     },
-    transmissions::sockets::{peek, receive, send},
+    transmissions::sockets::SipSocket,
 };
-use std::time::Duration;
 
 pub fn sip_event_loop(
     c_conf: &JSONConfiguration,
@@ -31,40 +33,33 @@ pub fn sip_event_loop(
     tokio::spawn(async move {
         let dialog_state = state;
 
-        let mut socket = UdpSocket::bind(format!("0.0.0.0:{}", 5060)).unwrap();
-        let _io_result = socket.set_read_timeout(Some(Duration::new(1, 0)));
-        socket
-            .connect(format!("{}:{}", &conf.sip_server, &conf.sip_port))
-            .expect("connect function failed");
-
         let verbosity: Verbosity;
         let mut sip_buffer = [0_u8; 65535];
-        {
+        let mut socket = {
             let settings = arc_settings.lock().unwrap();
             verbosity = settings.verbosity.clone();
-        }
+            SipSocket::bind(&conf, &settings.ip, conf.local_sip_port)
+        };
 
         'thread: loop {
-            // peek on the socket, for pending messages
-            let mut maybe_msg: Option<SipMessage> = None;
-            {
-                let packets_queued = peek(&mut socket, &mut sip_buffer);
-
-                if packets_queued > 0 {
-                    maybe_msg = match receive(&mut socket, &mut sip_buffer, &verbosity) {
-                        Ok(buf) => Some(buf),
-                        Err(_) => None,
-                    };
-                }
-            }
+            // pick up a pending message, if any
+            let maybe_msg: Option<SipMessage> = socket.receive(&mut sip_buffer, &verbosity);
 
             // distribute message on the correct process
             if let Some(..) = maybe_msg {
                 let msg = maybe_msg.unwrap();
+                let direction = {
+                    let mut state = dialog_state.lock().unwrap();
+                    if let rsip::SipMessage::Response(response) = &msg {
+                        state.update_transaction(response, answered);
+                    }
+                    // anything outside a known dialog is a new inbound one
+                    state.get_direction(&msg).unwrap_or(Direction::Inbound)
+                };
                 let mut settings = arc_settings.lock().unwrap();
                 {
-                    info!("match flow, {}", settings.flow);
-                    match settings.flow {
+                    info!("match flow, {}", direction);
+                    match direction {
                         Direction::Inbound => match msg {
                             rsip::SipMessage::Request(request) => process_request_inbound(
                                 &request,
@@ -94,15 +89,36 @@ pub fn sip_event_loop(
                 }
             }
 
+            answer_pending(&conf, &dialog_state, &arc_settings.lock().unwrap());
+            cancel_hung_up(&dialog_state);
+            complete_transfers(&dialog_state);
+            notify_referrers(&dialog_state);
+            refresh_subscriptions(&dialog_state);
+            refresh_sessions(&dialog_state);
+
             let mut state = dialog_state.lock().unwrap();
+            for retransmission in state.poll_transactions() {
+                socket.send(&retransmission, &verbosity);
+            }
+
             let channel = state.get_sip_channel().unwrap();
 
-            if let Ok(data) = channel.1.try_recv() {
+            while let Ok(data) = channel.1.try_recv() {
                 if data.exit {
                     break 'thread;
                 }
-                send(&mut socket, &data.event.unwrap(), &verbosity);
+                socket.send(&data.event.unwrap(), &verbosity);
             }
         }
     })
 }
+
+/// Hands a response over to the flow of the request it answers
+fn answered(dg: &mut Dialog, transaction: &Transaction, response: &Response) {
+    match transaction.method() {
+        Some(Method::Message) => message_answered(dg, response),
+        Some(Method::Subscribe) => subscribe_answered(dg, response),
+        Some(method) => call_answered(dg, method, response),
+        None => {}
+    }
+}
@@ -3,14 +3,14 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use chrono::prelude::*;
 use rsip::SipMessage;
 use uuid::Uuid;
 
 use crate::{
+    commands::helper::get_branch,
     config::JSONConfiguration,
     state::{
-        dialogs::{Direction, Register, State, Transactions},
+        dialogs::{Direction, Register, State},
         options::SipOptions,
         transactions::{Transaction, TransactionType},
     },
@@ -21,18 +21,9 @@ use crate::{
 /// as well as sending the first unauthorized message
 pub fn register_ua(state: &Arc<Mutex<State>>, conf: &JSONConfiguration, ip: &IpAddr) {
     info!("starting registration process");
-    let now = Utc::now();
 
     let mut register = SipOptions {
-        branch: format!(
-            "z9hG4bK{}{}{}{}{}{}",
-            now.month(),
-            now.day(),
-            now.hour(),
-            now.minute(),
-            now.second(),
-            now.timestamp_millis()
-        ),
+        branch: get_branch(),
         extension: conf.extension.to_string(),
         ip: ip.to_string(),
         md5: None,
@@ -50,6 +41,8 @@ pub fn register_ua(state: &Arc<Mutex<State>>, conf: &JSONConfiguration, ip: &IpA
         qop: false,
         opaque: None,
         realm: "".to_string(),
+        local_port: conf.local_sip_port.to_string(),
+        transport: conf.transport,
     };
 
     let mut transaction: Option<String> = None;
@@ -58,29 +51,26 @@ pub fn register_ua(state: &Arc<Mutex<State>>, conf: &JSONConfiguration, ip: &IpA
         let mut locked_state = state.lock().unwrap();
         let mut registrations = locked_state.get_registrations().unwrap();
 
-        registrations.push(Register {
-            call_id: Uuid::new_v4().to_string(),
-            diag_type: Direction::Inbound,
-            local_tag: Uuid::new_v4().to_string(),
-            remote_tag: None,
-            transactions: Transactions::new(),
-            time: Local::now(),
-        });
+        registrations.push(Register::new(
+            Direction::Inbound,
+            Uuid::new_v4().to_string(),
+            Uuid::new_v4().to_string(),
+        ));
 
         for dg in registrations.iter_mut() {
             if matches!(dg.diag_type, Direction::Inbound) {
                 let mut transactions = dg.transactions.get_transactions().unwrap();
-                let local_transaction = Transaction {
-                    object: register.clone(),
-                    local: Some(register.set_initial_register()),
-                    remote: None,
-                    tr_type: TransactionType::Typical,
-                };
+                let local_transaction = Transaction::new(
+                    TransactionType::Typical,
+                    register.clone(),
+                    Some(register.set_initial_register()),
+                );
                 transactions.push(local_transaction.clone());
 
                 register.msg = Some(local_transaction.object.set_initial_register());
 
                 let loop_transaction = transactions.last_mut().unwrap();
+                loop_transaction.start(&conf.sip_server, conf.sip_port);
                 transaction = Some(loop_transaction.local.as_ref().unwrap().to_string());
                 break;
             }
@@ -121,6 +111,7 @@ pub fn keep_alive(state: Arc<Mutex<State>>, conf: &JSONConfiguration) {
             let transaction = transactions.last_mut().unwrap();
             sip = Some(transaction.object.keep_alive());
             transaction.local = sip.clone();
+            transaction.start(&conf.sip_server, conf.sip_port);
         }
     }
 
@@ -156,6 +147,7 @@ pub fn unregister_ua(state: Arc<Mutex<State>>, conf: &JSONConfiguration) {
             let transaction = transactions.last_mut().unwrap();
             sip = Some(transaction.object.unregister());
             transaction.local = sip.clone();
+            transaction.start(&conf.sip_server, conf.sip_port);
         }
     }
 
@@ -1,7 +1,19 @@
-use crate::transmissions::sockets::{MpscBase, SocketV4};
+use crate::{
+    commands::{
+        helper::{get_other_header, get_sdp_direction, get_sdp_with_direction, get_tag},
+        reject::reject,
+    },
+    parsers::{has_option_tag, negotiate_session_timer, parse_session_expires},
+    transmissions::sockets::{MpscBase, SocketV4},
+};
 
-use super::transactions::Transaction;
+use super::transactions::{Transaction, TransactionType};
 use chrono::prelude::*;
+use rsip::{
+    prelude::{HeadersExt, ToTypedHeader, UntypedHeader},
+    Request, Response, SipMessage,
+};
+use serde::Serialize;
 use std::{
     error::Error,
     fmt::{self, Display, Formatter},
@@ -18,10 +30,331 @@ pub struct Dialog {
     pub remote_tag: Option<String>,
     pub transactions: Transactions,
     pub time: DateTime<Local>,
+    pub state: DialogState,
+    /// Our side of the dialog, the From of the requests we send
+    pub local_uri: Option<String>,
+    /// The other side of the dialog, the To of the requests we send
+    pub remote_uri: Option<String>,
+    /// Contact of the other side, the Request-URI of the requests we send
+    pub remote_target: Option<String>,
+    /// Route headers of the requests we send
+    pub route_set: Vec<String>,
+    /// When a ringing inbound call gets answered
+    pub answer_at: Option<DateTime<Local>>,
+    /// Put on hold by us, no audio is sent while held
+    pub on_hold: bool,
+    /// Put on hold by the other side, its last offer was `sendonly` or `inactive`
+    pub remote_hold: bool,
+    /// Session version of our SDP, it goes up whenever our SDP changes (RFC 3264)
+    pub sdp_version: u64,
+    /// Media direction of the last SDP we sent, the first one is `sendrecv`
+    pub sdp_direction: String,
+    /// Progress of a transfer we asked for with a REFER
+    pub transfer: Option<TransferState>,
+    /// Call-id of the consultation call of an attended transfer,
+    /// this call gets transferred to it once it is answered
+    pub consultation: Option<String>,
+    /// Call we originated because the other side sent us a REFER
+    pub referral: Option<Referral>,
+    /// Where the other side receives audio, from its SDP
+    pub remote_media: Option<(String, u16)>,
+    /// Payload type of the telephone-events the other side accepts
+    pub dtmf_payload: Option<u8>,
+    /// DTMF received on the call, oldest first
+    pub digits: Vec<Digit>,
+    /// When our subscription, or the session of a call we refresh, gets refreshed
+    pub refresh_at: Option<DateTime<Local>>,
+    /// RSeq of the last reliable provisional response, the one we sent
+    /// on an inbound call or the one we PRACKed on an outbound call
+    pub rseq: Option<u32>,
+    /// Session interval negotiated for the call (RFC 4028), `None` without session timer
+    pub session_expires: Option<u32>,
+    /// We send the session refreshes, otherwise the other side does
+    pub session_refresher: bool,
+    /// When the session ends without a refresh
+    pub session_expires_at: Option<DateTime<Local>>,
+    /// The other side allows UPDATE, session refreshes use it instead of a re-INVITE
+    pub update_allowed: bool,
+    /// Hung up before the INVITE got any response, a CANCEL
+    /// may only follow a provisional response (RFC 3261 9.1)
+    pub cancel_pending: bool,
 }
 
 pub type Register = Dialog;
 
+/// Subscription to an event package of a resource (RFC 6665), a dialog of its own
+pub struct Subscription {
+    pub dialog: Dialog,
+    /// Event package subscribed to
+    pub event: String,
+    /// Last state the notifier reported of the extension a dialog
+    /// event package subscription monitors
+    pub line_state: Option<LineState>,
+}
+
+impl Subscription {
+    pub fn new(event: &str, dialog: Dialog) -> Self {
+        Self {
+            dialog,
+            event: event.to_string(),
+            line_state: None,
+        }
+    }
+}
+
+impl Dialog {
+    pub fn new(diag_type: Direction, call_id: String, local_tag: String) -> Self {
+        Self {
+            diag_type,
+            call_id,
+            local_tag,
+            remote_tag: None,
+            transactions: Transactions::new(),
+            time: Local::now(),
+            state: DialogState::Early,
+            local_uri: None,
+            remote_uri: None,
+            remote_target: None,
+            route_set: vec![],
+            answer_at: None,
+            on_hold: false,
+            remote_hold: false,
+            sdp_version: 0,
+            sdp_direction: "sendrecv".to_string(),
+            transfer: None,
+            consultation: None,
+            referral: None,
+            remote_media: None,
+            dtmf_payload: None,
+            digits: vec![],
+            refresh_at: None,
+            rseq: None,
+            session_expires: None,
+            session_refresher: false,
+            session_expires_at: None,
+            update_allowed: false,
+            cancel_pending: false,
+        }
+    }
+
+    /// Starts the session timer (RFC 4028) over, a refresher refreshes halfway through
+    /// the interval and either side gives up shortly before it runs out
+    pub fn start_session_timer(&mut self, interval: u32, refresher: bool) {
+        let now = Local::now();
+        let interval = interval as i64;
+
+        self.session_expires = Some(interval as u32);
+        self.session_refresher = refresher;
+        self.refresh_at = match refresher {
+            true => Some(now + chrono::Duration::seconds(interval / 2)),
+            false => None,
+        };
+        self.session_expires_at =
+            Some(now + chrono::Duration::seconds(interval - std::cmp::min(32, interval / 3)));
+    }
+
+    /// The call runs on without a session timer
+    pub fn stop_session_timer(&mut self) {
+        self.session_expires = None;
+        self.refresh_at = None;
+        self.session_expires_at = None;
+    }
+
+    /// Takes the session timer our 2xx agreed to for `request`, the initial INVITE
+    /// or a refresh of the other side
+    pub fn refreshed_by(&mut self, request: &Request) {
+        if get_other_header(&request.headers, "Allow").is_some() {
+            self.update_allowed = has_option_tag(&request.headers, "Allow", "UPDATE");
+        }
+        match negotiate_session_timer(&request.headers) {
+            Some((interval, refresher)) => self.start_session_timer(interval, refresher == "uas"),
+            None => self.stop_session_timer(),
+        }
+    }
+
+    /// Takes the session timer of the 2xx to our INVITE or UPDATE,
+    /// a 2xx without Session-Expires turns the session timer off
+    pub fn on_refresh_response(&mut self, response: &Response) {
+        if get_other_header(&response.headers, "Allow").is_some() {
+            self.update_allowed = has_option_tag(&response.headers, "Allow", "UPDATE");
+        }
+        let session_expires = get_other_header(&response.headers, "Session-Expires")
+            .and_then(|session_expires| parse_session_expires(&session_expires));
+        match session_expires {
+            Some((interval, refresher)) => {
+                self.start_session_timer(interval, refresher.as_deref() != Some("uas"))
+            }
+            None => self.stop_session_timer(),
+        }
+    }
+
+    /// Takes the media direction of an offer of ours that got answered,
+    /// a held call offered `sendonly` or `inactive`
+    pub fn offer_answered(&mut self, offer: &[u8]) {
+        if !offer.is_empty() {
+            self.on_hold = matches!(get_sdp_direction(offer), "sendonly" | "inactive");
+        }
+    }
+
+    /// Takes the media direction of an offer of the other side
+    pub fn offer_received(&mut self, offer: &[u8]) {
+        if !offer.is_empty() {
+            self.remote_hold = matches!(get_sdp_direction(offer), "sendonly" | "inactive");
+        }
+    }
+
+    /// Our SDP offering or answering media in `direction`, an SDP the same as
+    /// the last one keeps its version
+    pub fn get_local_sdp(&mut self, ip: &str, direction: &str) -> String {
+        if self.sdp_direction != direction {
+            self.sdp_version += 1;
+            self.sdp_direction = direction.to_string();
+        }
+
+        get_sdp_with_direction(ip, self.sdp_version, direction)
+    }
+
+    /// CSeq for the next request we send, one above any request sent so far
+    pub fn next_cseq(&mut self) -> u32 {
+        let transactions = self.transactions.get_transactions().unwrap();
+        let last = transactions
+            .iter()
+            .filter_map(|tr| match &tr.local {
+                Some(local @ SipMessage::Request(_)) => local.cseq_header().ok(),
+                _ => None,
+            })
+            .filter_map(|cseq| cseq.typed().ok())
+            .map(|cseq| cseq.seq)
+            .max();
+
+        last.unwrap_or(0) + 1
+    }
+
+    /// Whether a request we received belongs to this dialog,
+    /// our tag is in the To header and theirs in the From
+    pub fn matches_request(&self, request: &Request) -> bool {
+        let (call_id, from_tag, to_tag) = get_dialog_id(request);
+        self.matches(call_id, to_tag, from_tag)
+    }
+
+    /// Whether a response we received belongs to this dialog,
+    /// our tag is in the From header and theirs in the To
+    pub fn matches_response(&self, response: &Response) -> bool {
+        let (call_id, from_tag, to_tag) = get_dialog_id(response);
+        self.matches(call_id, from_tag, to_tag)
+    }
+
+    /// A missing tag matches, requests opening a dialog carry no To tag
+    /// and the remote tag of an early dialog is not known yet
+    fn matches(
+        &self,
+        call_id: Option<String>,
+        local_tag: Option<String>,
+        remote_tag: Option<String>,
+    ) -> bool {
+        call_id.as_deref() == Some(self.call_id.as_str())
+            && local_tag.map_or(true, |tag| tag == self.local_tag)
+            && match (&self.remote_tag, remote_tag) {
+                (Some(ours), Some(theirs)) => *ours == theirs,
+                _ => true,
+            }
+    }
+}
+
+/// Call-ID, From tag and To tag of a message
+fn get_dialog_id<T: HeadersExt>(msg: &T) -> (Option<String>, Option<String>, Option<String>) {
+    let call_id = msg
+        .call_id_header()
+        .ok()
+        .map(|call_id| call_id.value().to_string());
+    let from_tag = msg
+        .from_header()
+        .ok()
+        .and_then(|from| from.typed().ok())
+        .and_then(|from| get_tag(&from.params));
+    let to_tag = msg
+        .to_header()
+        .ok()
+        .and_then(|to| to.typed().ok())
+        .and_then(|to| get_tag(&to.params));
+
+    (call_id, from_tag, to_tag)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DialogState {
+    Early,
+    Confirmed,
+    Terminated,
+}
+
+/// Call originated on behalf of a REFER, along with the last status NOTIFYed about it
+#[derive(Debug, Clone)]
+pub struct Referral {
+    pub call_id: String,
+    pub reported: Option<u16>,
+}
+
+/// DTMF digit received over RTP or with an INFO, duration in milliseconds
+#[derive(Debug, Clone, Serialize)]
+pub struct Digit {
+    pub digit: char,
+    pub duration: u16,
+}
+
+/// Call as listed over HTTP, an inbound call still `early` is ringing
+#[derive(Debug, Clone, Serialize)]
+pub struct CallSummary {
+    pub call_id: String,
+    pub direction: Direction,
+    pub state: DialogState,
+    pub remote_uri: Option<String>,
+    pub on_hold: bool,
+}
+
+/// MESSAGE received, kept in the inbox until read over HTTP
+#[derive(Debug, Clone, Serialize)]
+pub struct InstantMessage {
+    pub from: String,
+    pub content_type: Option<String>,
+    pub body: String,
+    pub received: String,
+}
+
+/// Message-waiting status of our mailbox (RFC 3842), the urgent messages
+/// are counted in `new` and `old` as well
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MessageSummary {
+    pub waiting: bool,
+    pub account: Option<String>,
+    pub new: u32,
+    pub old: u32,
+    pub urgent_new: u32,
+    pub urgent_old: u32,
+}
+
+/// State of a monitored extension, as its dialog-info (RFC 4235) reports it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineState {
+    Idle,
+    Ringing,
+    Confirmed,
+}
+
+/// Transfer progress, as reported by the NOTIFYs that follow a REFER
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TransferState {
+    Requested,
+    Accepted,
+    Trying(u16),
+    Succeeded(u16),
+    Failed(u16),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Direction {
     Inbound,
     Outbound,
@@ -40,6 +373,9 @@ impl Display for Direction {
 pub struct State {
     dialog: Arc<Mutex<Vec<Dialog>>>,
     reg: Arc<Mutex<Vec<Register>>>,
+    subs: Arc<Mutex<Vec<Subscription>>>,
+    inbox: Arc<Mutex<Vec<InstantMessage>>>,
+    mwi: Arc<Mutex<Option<MessageSummary>>>,
     sip: Arc<Mutex<(Sender<UdpCommand>, Receiver<UdpCommand>)>>,
     rtp: Arc<Mutex<(Sender<UdpCommand>, Receiver<UdpCommand>)>>,
 }
@@ -80,6 +416,9 @@ impl State {
         State {
             dialog: Arc::new(Mutex::new(vec![])),
             reg: Arc::new(Mutex::new(vec![])),
+            subs: Arc::new(Mutex::new(vec![])),
+            inbox: Arc::new(Mutex::new(vec![])),
+            mwi: Arc::new(Mutex::new(None)),
             sip: Arc::new(Mutex::new((s_a, r_a))),
             rtp: Arc::new(Mutex::new((s_b, r_b))),
         }
@@ -93,6 +432,18 @@ impl State {
         Ok(self.reg.lock()?)
     }
 
+    pub fn get_subscriptions(&mut self) -> Result<MutexGuard<Vec<Subscription>>, StateLockError> {
+        Ok(self.subs.lock()?)
+    }
+
+    pub fn get_inbox(&mut self) -> Result<MutexGuard<Vec<InstantMessage>>, StateLockError> {
+        Ok(self.inbox.lock()?)
+    }
+
+    pub fn get_mwi(&mut self) -> Result<MutexGuard<Option<MessageSummary>>, StateLockError> {
+        Ok(self.mwi.lock()?)
+    }
+
     pub fn get_sip_channel(&mut self) -> Result<MutexGuard<SRUdpCommand>, StateLockError> {
         Ok(self.sip.lock()?)
     }
@@ -101,6 +452,217 @@ impl State {
     pub fn get_rtp_channel(&mut self) -> Result<MutexGuard<SRUdpCommand>, StateLockError> {
         Ok(self.rtp.lock()?)
     }
+
+    /// Fires the timers of every dialog, registration and subscription transaction,
+    /// returns the requests that are due for a retransmission
+    pub fn poll_transactions(&mut self) -> Vec<SocketV4> {
+        let now = Local::now();
+        let mut due: Vec<SocketV4> = vec![];
+
+        poll_dialogs(self.get_dialogs().unwrap().iter_mut(), now, &mut due);
+        poll_dialogs(self.get_registrations().unwrap().iter_mut(), now, &mut due);
+        poll_dialogs(
+            self.get_subscriptions()
+                .unwrap()
+                .iter_mut()
+                .map(|subscription| &mut subscription.dialog),
+            now,
+            &mut due,
+        );
+
+        due
+    }
+
+    /// Whether the call was put on hold by us
+    pub fn is_on_hold(&mut self, call_id: &str) -> bool {
+        self.get_dialogs()
+            .unwrap()
+            .iter()
+            .any(|dg| dg.call_id == call_id && dg.on_hold)
+    }
+
+    /// Payload type of the telephone-events negotiated for the call
+    pub fn get_dtmf_payload(&mut self, call_id: &str) -> Option<u8> {
+        self.get_dialogs()
+            .unwrap()
+            .iter()
+            .find(|dg| dg.call_id == call_id)
+            .and_then(|dg| dg.dtmf_payload)
+    }
+
+    pub fn add_digit(&mut self, call_id: &str, digit: Digit) {
+        if let Some(dg) = self
+            .get_dialogs()
+            .unwrap()
+            .iter_mut()
+            .find(|dg| dg.call_id == call_id)
+        {
+            info!("dtmf {} received on {}", digit.digit, call_id);
+            dg.digits.push(digit);
+        }
+    }
+
+    /// Digits received on the call, `None` for an unknown call
+    pub fn get_digits(&mut self, call_id: &str) -> Option<Vec<Digit>> {
+        self.get_dialogs()
+            .unwrap()
+            .iter()
+            .find(|dg| dg.call_id == call_id)
+            .map(|dg| dg.digits.clone())
+    }
+
+    /// Calls that have not ended yet, oldest first
+    pub fn get_calls(&mut self) -> Vec<CallSummary> {
+        self.get_dialogs()
+            .unwrap()
+            .iter()
+            .filter(|dg| dg.state != DialogState::Terminated)
+            .map(|dg| CallSummary {
+                call_id: dg.call_id.clone(),
+                direction: dg.diag_type,
+                state: dg.state,
+                remote_uri: dg.remote_uri.clone(),
+                on_hold: dg.on_hold,
+            })
+            .collect()
+    }
+
+    /// Direction of the call, registration or subscription a message belongs to,
+    /// `None` when the message does not belong to any known dialog
+    pub fn get_direction(&mut self, msg: &SipMessage) -> Option<Direction> {
+        let dialogs = self.dialog.lock().unwrap();
+        let registrations = self.reg.lock().unwrap();
+        let subscriptions = self.subs.lock().unwrap();
+
+        dialogs
+            .iter()
+            .rev()
+            .chain(registrations.iter().rev())
+            .chain(
+                subscriptions
+                    .iter()
+                    .rev()
+                    .map(|subscription| &subscription.dialog),
+            )
+            .find(|dg| match msg {
+                SipMessage::Request(request) => dg.matches_request(request),
+                SipMessage::Response(response) => dg.matches_response(response),
+            })
+            .map(|dg| dg.diag_type)
+    }
+
+    /// Passes a response to the client transaction that sent the request,
+    /// `answered` takes what the response means to the flow of the request
+    pub fn update_transaction<F>(&mut self, response: &Response, answered: F)
+    where
+        F: FnOnce(&mut Dialog, &Transaction, &Response),
+    {
+        let mut dialogs = self.dialog.lock().unwrap();
+        let mut registrations = self.reg.lock().unwrap();
+        let mut subscriptions = self.subs.lock().unwrap();
+
+        let all = dialogs.iter_mut().chain(registrations.iter_mut()).chain(
+            subscriptions
+                .iter_mut()
+                .map(|subscription| &mut subscription.dialog),
+        );
+        for dg in all {
+            let mut transactions = dg.transactions.get_transactions().unwrap();
+            if let Some(transaction) = transactions
+                .iter_mut()
+                .find(|tr| tr.matches_response(response))
+            {
+                transaction.on_response(&response.status_code);
+                // an answered BYE ends the dialog
+                if transaction.is_answered_bye() {
+                    dg.state = DialogState::Terminated;
+                }
+                let transaction = transaction.clone();
+                drop(transactions);
+                answered(dg, &transaction, response);
+                return;
+            }
+        }
+    }
+}
+
+/// Rejects the INVITE of `server` with the final response in its `result`,
+/// retransmitted until the ACK like any other final response
+fn reject_unpracked(server: &mut Transaction, local_tag: &str) -> Option<SocketV4> {
+    let status_code = server.result.take()?;
+    let request = match &server.remote {
+        Some(SipMessage::Request(request)) => request.clone(),
+        _ => return None,
+    };
+    let (ip, port) = server.destination.clone()?;
+
+    server.local = Some(reject(&request, status_code, Some(local_tag)));
+    server.start_server(&ip, port);
+
+    Some(SocketV4 {
+        ip,
+        port,
+        bytes: server.local.as_ref()?.to_string().as_bytes().to_vec(),
+    })
+}
+
+fn poll_dialogs<'a>(
+    dialogs: impl IntoIterator<Item = &'a mut Dialog>,
+    now: DateTime<Local>,
+    due: &mut Vec<SocketV4>,
+) {
+    for dg in dialogs {
+        let mut unacknowledged: Option<Transaction> = None;
+        let mut unanswered = false;
+        {
+            let mut transactions = dg.transactions.get_transactions().unwrap();
+            for transaction in transactions.iter_mut() {
+                if let Some(event) = transaction.poll(now) {
+                    due.push(event);
+                }
+                if transaction.is_unpracked() {
+                    warn!("no PRACK received for {}, rejecting the call", dg.call_id);
+                    due.extend(reject_unpracked(transaction, &dg.local_tag));
+                    dg.state = DialogState::Terminated;
+                }
+                if transaction.is_unacknowledged() {
+                    unacknowledged = Some(transaction.clone());
+                }
+                unanswered |= transaction.is_unanswered_invite();
+            }
+        }
+
+        // our INVITE got no final response, no dialog was ever set up to hang up
+        if unanswered && dg.state == DialogState::Early {
+            warn!("no answer for {}, 408 Request Timeout", dg.call_id);
+            dg.state = DialogState::Terminated;
+        }
+
+        // the caller never acknowledged our answer, the call is torn down
+        if let Some(server) = unacknowledged {
+            if dg.state != DialogState::Terminated {
+                warn!("no ACK received for {}, hanging up", dg.call_id);
+                dg.state = DialogState::Terminated;
+
+                let cseq = dg.next_cseq();
+                let mut bye = Transaction::new(
+                    TransactionType::Typical,
+                    server.object.clone(),
+                    Some(server.object.create_bye(dg, cseq)),
+                );
+
+                if let Some((ip, port)) = server.destination {
+                    bye.start(&ip, port);
+                    due.push(SocketV4 {
+                        ip,
+                        port,
+                        bytes: bye.local.as_ref().unwrap().to_string().as_bytes().to_vec(),
+                    });
+                }
+                dg.transactions.get_transactions().unwrap().push(bye);
+            }
+        }
+    }
 }
 
 /// Collection of Transactions
@@ -140,3 +702,141 @@ impl Transactions {
         Ok(self.dialog.lock()?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        state::{
+            dialogs::{poll_dialogs, Dialog, DialogState, Direction, State, Subscription},
+            transactions::{tests::options, Transaction, TransactionState, TransactionType},
+        },
+        transmissions::timers::Timer,
+    };
+    use chrono::prelude::*;
+    use rsip::SipMessage;
+    use std::{convert::TryFrom, sync::mpsc::channel};
+
+    fn bye(call_id: &str, from_tag: &str, to_tag: &str) -> SipMessage {
+        SipMessage::try_from(format!(
+            "BYE sip:1001@10.0.0.1 SIP/2.0\r\nVia: SIP/2.0/UDP 10.0.0.2:5060;branch=z9hG4bK1\r\nFrom: <sip:1002@10.0.0.2>;tag={}\r\nTo: <sip:1001@10.0.0.1>;tag={}\r\nCall-ID: {}\r\nCSeq: 2 BYE\r\nContent-Length: 0\r\n\r\n",
+            from_tag, to_tag, call_id
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn requests_find_their_own_call() {
+        let mut state = State::new(channel(), channel());
+        let mut inbound = Dialog::new(Direction::Inbound, "a".to_string(), "ours-a".to_string());
+        inbound.remote_tag = Some("theirs-a".to_string());
+        let mut outbound = Dialog::new(Direction::Outbound, "b".to_string(), "ours-b".to_string());
+        outbound.remote_tag = Some("theirs-b".to_string());
+        state.get_dialogs().unwrap().push(inbound);
+        state.get_dialogs().unwrap().push(outbound);
+
+        assert_eq!(
+            state.get_direction(&bye("a", "theirs-a", "ours-a")),
+            Some(Direction::Inbound)
+        );
+        assert_eq!(
+            state.get_direction(&bye("b", "theirs-b", "ours-b")),
+            Some(Direction::Outbound)
+        );
+        assert_eq!(state.get_direction(&bye("a", "theirs-a", "ours-b")), None);
+    }
+
+    #[test]
+    fn requests_find_their_subscription() {
+        let mut state = State::new(channel(), channel());
+        let dialog = Dialog::new(Direction::Outbound, "s".to_string(), "ours-s".to_string());
+        state
+            .get_subscriptions()
+            .unwrap()
+            .push(Subscription::new("dialog", dialog));
+
+        assert_eq!(
+            state.get_direction(&bye("s", "theirs-s", "ours-s")),
+            Some(Direction::Outbound)
+        );
+        assert_eq!(state.get_direction(&bye("s", "theirs-s", "ours-a")), None);
+    }
+
+    #[test]
+    fn ringing_calls_are_listed() {
+        let mut state = State::new(channel(), channel());
+        let ringing = Dialog::new(Direction::Inbound, "a".to_string(), "ours-a".to_string());
+        let mut ended = Dialog::new(Direction::Outbound, "b".to_string(), "ours-b".to_string());
+        ended.state = DialogState::Terminated;
+        state.get_dialogs().unwrap().push(ringing);
+        state.get_dialogs().unwrap().push(ended);
+
+        let calls = serde_json::to_value(state.get_calls()).unwrap();
+        assert_eq!(calls.as_array().unwrap().len(), 1);
+        assert_eq!(calls[0]["call_id"], "a");
+        assert_eq!(calls[0]["direction"], "inbound");
+        assert_eq!(calls[0]["state"], "early");
+    }
+
+    #[test]
+    fn unanswered_invite_fails_without_bye() {
+        let mut dg = Dialog::new(Direction::Outbound, "call".to_string(), "tag".to_string());
+        let mut invite = Transaction::new(TransactionType::Invite, options(), None);
+        invite.start("sip.server.com", 5060);
+        dg.transactions.get_transactions().unwrap().push(invite);
+
+        let mut due = vec![];
+        let mut dialogs = vec![dg];
+        poll_dialogs(&mut dialogs, Local::now() + Timer::B.duration(), &mut due);
+
+        assert!(due.is_empty());
+        assert_eq!(dialogs[0].state, DialogState::Terminated);
+        assert_eq!(dialogs[0].transactions.get_transactions().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn unpracked_call_is_rejected() {
+        let mut dg = Dialog::new(Direction::Inbound, "call".to_string(), "ours".to_string());
+        let mut server = Transaction::new(TransactionType::InviteServer, options(), None);
+        server.remote = Some(
+            SipMessage::try_from(
+                "INVITE sip:1004@10.0.0.1 SIP/2.0\r\nVia: SIP/2.0/UDP 10.0.0.2:5060;branch=z9hG4bK1\r\nFrom: <sip:1002@10.0.0.2>;tag=theirs\r\nTo: <sip:1004@10.0.0.1>\r\nCall-ID: call\r\nCSeq: 1 INVITE\r\nContent-Length: 0\r\n\r\n"
+                    .to_string(),
+            )
+            .unwrap(),
+        );
+        server.start_reliable("10.0.0.2", 5060);
+        dg.transactions.get_transactions().unwrap().push(server);
+
+        let mut due = vec![];
+        let mut dialogs = vec![dg];
+        poll_dialogs(&mut dialogs, Local::now() + Timer::B.duration(), &mut due);
+
+        assert_eq!(due.len(), 1);
+        match SipMessage::try_from(due.remove(0).bytes).unwrap() {
+            SipMessage::Response(response) => assert_eq!(response.status_code.code(), 500),
+            SipMessage::Request(_) => panic!("expected a 500"),
+        }
+        assert_eq!(dialogs[0].state, DialogState::Terminated);
+        assert_eq!(
+            dialogs[0].transactions.get_transactions().unwrap()[0].state,
+            TransactionState::Completed
+        );
+    }
+
+    #[test]
+    fn sdp_version_follows_changes() {
+        let mut dg = Dialog::new(Direction::Outbound, "call".to_string(), "tag".to_string());
+        let version = |sdp: String| sdp.lines().nth(1).unwrap().to_string();
+
+        let refresh = version(dg.get_local_sdp("10.0.0.1", "sendrecv"));
+        assert_eq!(refresh, version(dg.get_local_sdp("10.0.0.1", "sendrecv")));
+
+        let hold = version(dg.get_local_sdp("10.0.0.1", "sendonly"));
+        assert_ne!(hold, refresh);
+        assert_eq!(hold, version(dg.get_local_sdp("10.0.0.1", "sendonly")));
+        assert_eq!(dg.sdp_version, 1);
+
+        dg.get_local_sdp("10.0.0.1", "sendrecv");
+        assert_eq!(dg.sdp_version, 2);
+    }
+}
@@ -1,6 +1,6 @@
 use std::net::IpAddr;
 
-use super::dialogs::Direction;
+use crate::config::{AnswerPolicy, Transport};
 use rsip::SipMessage;
 
 #[derive(Clone)]
@@ -11,6 +11,8 @@ pub struct SipOptions {
     pub sip_port: String,
     pub branch: String,
     pub ip: String,
+    /// Port of our own SIP socket, in Via and Contact
+    pub local_port: String,
     pub md5: Option<String>,
     pub nonce: Option<String>,
     pub cnonce: Option<String>,
@@ -23,12 +25,14 @@ pub struct SipOptions {
     pub tag_local: String,
     pub tag_remote: Option<String>,
     pub realm: String,
+    pub transport: Transport,
 }
 
 pub struct SelfConfiguration {
     pub ip: IpAddr,
     pub verbosity: Verbosity,
-    pub flow: Direction,
+    /// Starts from the configured policy, can be switched at runtime
+    pub answer: AnswerPolicy,
 }
 
 #[allow(dead_code)]
@@ -1,5 +1,10 @@
 use super::options::SipOptions;
-use rsip::SipMessage;
+use crate::transmissions::{sockets::SocketV4, timers::Timer};
+use chrono::prelude::*;
+use rsip::{
+    prelude::{HeadersExt, ToTypedHeader},
+    Method, Request, Response, SipMessage, StatusCode,
+};
 
 /// SIP transaction model
 #[derive(Clone)]
@@ -8,6 +13,21 @@ pub struct Transaction {
     pub local: Option<SipMessage>,
     pub remote: Option<SipMessage>,
     pub object: SipOptions,
+    /// Branch of the top Via, identifies the transaction (RFC 3261 17.1.3)
+    pub branch: Option<String>,
+    pub state: TransactionState,
+    /// Where `local` gets retransmitted to
+    pub destination: Option<(String, u16)>,
+    /// Current retransmission interval (Timer A/E)
+    pub interval: chrono::Duration,
+    pub retransmit_at: Option<DateTime<Local>>,
+    /// Timer B/F while waiting for a final response, Timer D/K afterwards
+    pub timeout_at: Option<DateTime<Local>>,
+    /// Final status of the transaction, a transaction that timed out gets a 408
+    pub result: Option<StatusCode>,
+    /// Sent over a reliable transport, taken from `object`, so Timer A/E/G never
+    /// fire and Timer D/I/K are zero
+    pub reliable: bool,
 }
 
 pub trait Reset {
@@ -26,6 +46,488 @@ pub enum TransactionType {
     Typical,
     Invite,
     Ack,
+    /// An INVITE we received, `remote` holds the request and `local` our last response
+    InviteServer,
 }
 
-impl Transaction {}
+/// RFC 3261 17 transaction states
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TransactionState {
+    Calling,
+    Trying,
+    Proceeding,
+    Completed,
+    Confirmed,
+    Terminated,
+}
+
+impl Transaction {
+    pub fn new(tr_type: TransactionType, object: SipOptions, local: Option<SipMessage>) -> Self {
+        let state = match tr_type {
+            TransactionType::Invite => TransactionState::Calling,
+            TransactionType::InviteServer => TransactionState::Proceeding,
+            _ => TransactionState::Trying,
+        };
+
+        let branch = match &local {
+            Some(local @ SipMessage::Request(_)) => get_top_branch(local),
+            _ => None,
+        };
+
+        Self {
+            tr_type,
+            local,
+            remote: None,
+            reliable: object.transport.is_reliable(),
+            object,
+            branch,
+            state,
+            destination: None,
+            interval: Timer::T1.duration(),
+            retransmit_at: None,
+            timeout_at: None,
+            result: None,
+        }
+    }
+
+    /// Duration of a timer that absorbs retransmissions, over a reliable
+    /// transport there are none to wait for
+    fn absorb(&self, timer: Timer) -> chrono::Duration {
+        match self.reliable {
+            true => chrono::Duration::zero(),
+            false => timer.duration(),
+        }
+    }
+
+    /// Arms the timers for the request currently in `local`,
+    /// Timer A/B for an INVITE, Timer E/F for anything else,
+    /// only the timeout over a reliable transport
+    pub fn start(&mut self, ip: &str, port: u16) {
+        let now = Local::now();
+
+        self.branch = self.local.as_ref().and_then(get_top_branch);
+        self.destination = Some((ip.to_string(), port));
+        self.result = None;
+
+        match self.tr_type {
+            TransactionType::Invite => {
+                self.state = TransactionState::Calling;
+                self.interval = Timer::A.duration();
+                self.timeout_at = Some(now + Timer::B.duration());
+            }
+            _ => {
+                self.state = TransactionState::Trying;
+                self.interval = Timer::E.duration();
+                self.timeout_at = Some(now + Timer::F.duration());
+            }
+        }
+
+        self.retransmit_at = match self.reliable {
+            true => None,
+            false => Some(now + self.interval),
+        };
+    }
+
+    /// Arms Timer G/H for the final response in `local`,
+    /// it gets retransmitted until the ACK arrives, unless the transport is reliable
+    pub fn start_server(&mut self, ip: &str, port: u16) {
+        let now = Local::now();
+
+        self.destination = Some((ip.to_string(), port));
+        self.result = None;
+        self.state = TransactionState::Completed;
+        self.interval = Timer::G.duration();
+        self.retransmit_at = match self.reliable {
+            true => None,
+            false => Some(now + self.interval),
+        };
+        self.timeout_at = Some(now + Timer::H.duration());
+    }
+
+    /// Arms the retransmission of the reliable provisional response in `local`
+    /// (RFC 3262), the interval doubles until the PRACK arrives or 64*T1 pass
+    pub fn start_reliable(&mut self, ip: &str, port: u16) {
+        let now = Local::now();
+
+        self.destination = Some((ip.to_string(), port));
+        self.interval = Timer::T1.duration();
+        self.retransmit_at = Some(now + self.interval);
+        self.timeout_at = Some(now + Timer::B.duration());
+    }
+
+    /// The PRACK for the reliable provisional response in `local` arrived
+    pub fn on_prack(&mut self) {
+        if self.state == TransactionState::Proceeding {
+            self.retransmit_at = None;
+            self.timeout_at = None;
+        }
+    }
+
+    /// The ACK for the final response in `local` arrived
+    pub fn on_ack(&mut self) {
+        if self.state == TransactionState::Completed {
+            self.state = TransactionState::Confirmed;
+            self.retransmit_at = None;
+            self.timeout_at = Some(Local::now() + self.absorb(Timer::I));
+        }
+    }
+
+    /// Moves the transaction forward on a response to `local`
+    pub fn on_response(&mut self, status_code: &StatusCode) {
+        let now = Local::now();
+        let code = status_code.code();
+
+        match self.tr_type {
+            TransactionType::Invite => match (code, self.state) {
+                (100..=199, TransactionState::Calling) => {
+                    self.state = TransactionState::Proceeding;
+                    self.retransmit_at = None;
+                    self.timeout_at = None;
+                }
+                (200..=299, TransactionState::Calling | TransactionState::Proceeding) => {
+                    self.state = TransactionState::Terminated;
+                    self.retransmit_at = None;
+                    self.timeout_at = None;
+                    self.result = Some(status_code.clone());
+                }
+                (300..=699, TransactionState::Calling | TransactionState::Proceeding) => {
+                    self.state = TransactionState::Completed;
+                    self.retransmit_at = None;
+                    self.timeout_at = Some(now + self.absorb(Timer::D));
+                    self.result = Some(status_code.clone());
+                }
+                _ => {}
+            },
+            _ => match (code, self.state) {
+                (100..=199, TransactionState::Trying) => {
+                    self.state = TransactionState::Proceeding;
+                    self.interval = Timer::T2.duration();
+                    if !self.reliable {
+                        self.retransmit_at = Some(now + self.interval);
+                    }
+                }
+                (200..=699, TransactionState::Trying | TransactionState::Proceeding) => {
+                    self.state = TransactionState::Completed;
+                    self.retransmit_at = None;
+                    self.timeout_at = Some(now + self.absorb(Timer::K));
+                    self.result = Some(status_code.clone());
+                }
+                _ => {}
+            },
+        }
+    }
+
+    /// Fires any expired timer, returns the request if it is due for a retransmission
+    pub fn poll(&mut self, now: DateTime<Local>) -> Option<SocketV4> {
+        if let Some(timeout_at) = self.timeout_at {
+            // a reliable provisional response that never got its PRACK,
+            // the INVITE gets rejected with a 500 (RFC 3262 3)
+            if now >= timeout_at
+                && matches!(self.tr_type, TransactionType::InviteServer)
+                && self.state == TransactionState::Proceeding
+            {
+                warn!("no PRACK received for the reliable provisional response");
+                self.retransmit_at = None;
+                self.timeout_at = None;
+                self.result = Some(StatusCode::from(500));
+                return None;
+            }
+            if now >= timeout_at {
+                let unanswered = match self.tr_type {
+                    TransactionType::InviteServer => self.state == TransactionState::Completed,
+                    _ => matches!(
+                        self.state,
+                        TransactionState::Calling
+                            | TransactionState::Trying
+                            | TransactionState::Proceeding
+                    ),
+                };
+                if unanswered {
+                    warn!("transaction timed out, no response received");
+                    self.result = Some(StatusCode::RequestTimeout);
+                }
+                self.state = TransactionState::Terminated;
+                self.retransmit_at = None;
+                self.timeout_at = None;
+                return None;
+            }
+        }
+
+        let retransmit_at = self.retransmit_at?;
+        if now < retransmit_at {
+            return None;
+        }
+
+        self.interval = match (&self.tr_type, self.state) {
+            (TransactionType::Invite, _) => self.interval * 2,
+            (TransactionType::InviteServer, TransactionState::Proceeding) => self.interval * 2,
+            (_, TransactionState::Proceeding) => Timer::T2.duration(),
+            _ => std::cmp::min(self.interval * 2, Timer::T2.duration()),
+        };
+        self.retransmit_at = Some(now + self.interval);
+
+        let (ip, port) = self.destination.clone()?;
+
+        Some(SocketV4 {
+            ip,
+            port,
+            bytes: self.local.as_ref()?.to_string().as_bytes().to_vec(),
+        })
+    }
+
+    /// Whether the response belongs to the request currently in `local`,
+    /// same top Via branch and same CSeq method
+    pub fn matches_response(&self, response: &Response) -> bool {
+        let local = match &self.local {
+            Some(local @ SipMessage::Request(_)) => local,
+            _ => return false,
+        };
+
+        self.branch.is_some()
+            && get_top_branch(response) == self.branch
+            && get_cseq_method(response).is_some()
+            && get_cseq_method(response) == get_cseq_method(local)
+    }
+
+    /// Whether the request is a retransmission of the one in `remote`
+    pub fn matches_request(&self, request: &Request) -> bool {
+        let remote = match &self.remote {
+            Some(remote @ SipMessage::Request(_)) => remote,
+            _ => return false,
+        };
+
+        get_top_branch(request).is_some()
+            && get_top_branch(request) == get_top_branch(remote)
+            && get_cseq_method(request) == get_cseq_method(remote)
+    }
+
+    /// Whether the CANCEL targets the INVITE in `remote`, a CANCEL
+    /// shares the top Via branch of the request it cancels
+    pub fn matches_cancel(&self, request: &Request) -> bool {
+        let remote = match &self.remote {
+            Some(remote @ SipMessage::Request(_)) => remote,
+            _ => return false,
+        };
+
+        get_top_branch(request).is_some()
+            && get_top_branch(request) == get_top_branch(remote)
+            && get_cseq_method(remote) == Some(Method::Invite)
+    }
+
+    /// An INVITE server transaction whose final response was never acknowledged
+    pub fn is_unacknowledged(&self) -> bool {
+        matches!(self.tr_type, TransactionType::InviteServer)
+            && self.state == TransactionState::Terminated
+            && self.result == Some(StatusCode::RequestTimeout)
+    }
+
+    /// An INVITE we received whose reliable provisional response never got its PRACK,
+    /// it still needs the final response in `result`
+    pub fn is_unpracked(&self) -> bool {
+        matches!(self.tr_type, TransactionType::InviteServer)
+            && self.state == TransactionState::Proceeding
+            && self.result.is_some()
+    }
+
+    /// An INVITE we sent that never got a final response (Timer B),
+    /// there is no dialog to tear down, the call just failed with a 408
+    pub fn is_unanswered_invite(&self) -> bool {
+        matches!(self.tr_type, TransactionType::Invite)
+            && self.state == TransactionState::Terminated
+            && self.result == Some(StatusCode::RequestTimeout)
+    }
+
+    /// Method of the request in `local`
+    pub fn method(&self) -> Option<Method> {
+        match &self.local {
+            Some(local @ SipMessage::Request(_)) => get_cseq_method(local),
+            _ => None,
+        }
+    }
+
+    /// A BYE we sent that got a 2xx
+    pub fn is_answered_bye(&self) -> bool {
+        self.method() == Some(Method::Bye)
+            && self
+                .result
+                .as_ref()
+                .map_or(false, |code| (200..=299).contains(&code.code()))
+    }
+}
+
+fn get_top_branch<T: HeadersExt>(msg: &T) -> Option<String> {
+    msg.via_header()
+        .ok()
+        .and_then(|via| via.typed().ok())
+        .and_then(|via| via.branch().map(|branch| branch.to_string()))
+}
+
+fn get_cseq_method<T: HeadersExt>(msg: &T) -> Option<Method> {
+    msg.cseq_header()
+        .ok()
+        .and_then(|cseq| cseq.typed().ok())
+        .map(|cseq| cseq.method)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::{
+        config::Transport,
+        state::{
+            options::SipOptions,
+            transactions::{Transaction, TransactionState, TransactionType},
+        },
+        transmissions::timers::Timer,
+    };
+    use chrono::prelude::*;
+    use rsip::{Response, SipMessage, StatusCode};
+    use std::convert::TryFrom;
+
+    pub(crate) fn options() -> SipOptions {
+        SipOptions {
+            username: "it_doesnt_matter".to_string(),
+            extension: "1004".to_string(),
+            sip_server: "sip.server.com".to_string(),
+            sip_port: "5060".to_string(),
+            branch: "it_doesnt_matter".to_string(),
+            ip: "it_doesnt_matter".to_string(),
+            local_port: "5060".to_string(),
+            nonce: None,
+            md5: None,
+            msg: None,
+            cld: None,
+            call_id: "it_doesnt_matter".to_string(),
+            tag_local: "it_doesnt_matter".to_string(),
+            tag_remote: None,
+            cnonce: None,
+            nc: None,
+            qop: false,
+            opaque: None,
+            realm: "something".to_string(),
+            transport: Transport::Udp,
+        }
+    }
+
+    fn message(start_line: &str, branch: &str, method: &str) -> SipMessage {
+        SipMessage::try_from(format!(
+            "{}\r\nVia: SIP/2.0/UDP 10.0.0.1:5060;branch={}\r\nCall-ID: call\r\nCSeq: 1 {}\r\nContent-Length: 0\r\n\r\n",
+            start_line, branch, method
+        ))
+        .unwrap()
+    }
+
+    fn response(branch: &str, method: &str) -> Response {
+        match message("SIP/2.0 200 OK", branch, method) {
+            SipMessage::Response(response) => response,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn responses_match_by_branch_and_method() {
+        let invite = message("INVITE sip:1001@10.0.0.2 SIP/2.0", "z9hG4bK1", "INVITE");
+        let transaction = Transaction::new(TransactionType::Invite, options(), Some(invite));
+
+        assert!(transaction.matches_response(&response("z9hG4bK1", "INVITE")));
+        // the 200 to a CANCEL shares the branch of the INVITE it cancels
+        assert!(!transaction.matches_response(&response("z9hG4bK1", "CANCEL")));
+        assert!(!transaction.matches_response(&response("z9hG4bK2", "INVITE")));
+    }
+
+    #[test]
+    fn invite_times_out_with_408() {
+        let mut transaction = Transaction::new(TransactionType::Invite, options(), None);
+        transaction.start("sip.server.com", 5060);
+
+        transaction.poll(Local::now() + Timer::B.duration());
+
+        assert_eq!(transaction.state, TransactionState::Terminated);
+        assert_eq!(transaction.result, Some(StatusCode::RequestTimeout));
+    }
+
+    #[test]
+    fn reliable_provisional_until_prack() {
+        let mut transaction = Transaction::new(TransactionType::InviteServer, options(), None);
+        transaction.start_reliable("10.0.0.1", 5060);
+
+        let mut now = Local::now();
+        for _ in 0..5 {
+            now = now + transaction.interval;
+            transaction.poll(now);
+        }
+        assert_eq!(transaction.interval, Timer::T1.duration() * 32);
+
+        transaction.on_prack();
+        assert_eq!(transaction.retransmit_at, None);
+        assert_eq!(transaction.state, TransactionState::Proceeding);
+    }
+
+    #[test]
+    fn reliable_provisional_without_prack_fails() {
+        let mut transaction = Transaction::new(TransactionType::InviteServer, options(), None);
+        transaction.start_reliable("10.0.0.1", 5060);
+
+        assert!(transaction
+            .poll(Local::now() + Timer::B.duration())
+            .is_none());
+
+        assert!(transaction.is_unpracked());
+        assert_eq!(transaction.result, Some(StatusCode::from(500)));
+        assert_eq!(transaction.retransmit_at, None);
+        assert_eq!(transaction.timeout_at, None);
+    }
+
+    #[test]
+    fn non_invite_retransmissions_cap_at_t2() {
+        let mut transaction = Transaction::new(TransactionType::Typical, options(), None);
+        transaction.start("sip.server.com", 5060);
+
+        let mut now = Local::now();
+        for _ in 0..5 {
+            now = now + transaction.interval;
+            transaction.poll(now);
+        }
+
+        assert_eq!(transaction.state, TransactionState::Trying);
+        assert_eq!(transaction.interval, Timer::T2.duration());
+    }
+
+    #[test]
+    fn invite_stops_retransmitting_on_provisional() {
+        let mut transaction = Transaction::new(TransactionType::Invite, options(), None);
+        transaction.start("sip.server.com", 5060);
+
+        transaction.on_response(&StatusCode::Ringing);
+
+        assert_eq!(transaction.state, TransactionState::Proceeding);
+        assert!(transaction.retransmit_at.is_none());
+    }
+
+    #[test]
+    fn reliable_transport_only_times_out() {
+        let tcp = SipOptions {
+            transport: Transport::Tcp,
+            ..options()
+        };
+        let mut invite = Transaction::new(TransactionType::Invite, tcp.clone(), None);
+        invite.start("sip.server.com", 5060);
+
+        assert!(invite.reliable);
+        assert!(invite.retransmit_at.is_none());
+        assert!(invite.poll(Local::now() + Timer::A.duration()).is_none());
+
+        // Timer D is zero, the transaction ends with the next poll
+        invite.on_response(&StatusCode::BusyHere);
+        assert_eq!(invite.state, TransactionState::Completed);
+        invite.poll(Local::now());
+        assert_eq!(invite.state, TransactionState::Terminated);
+        assert_eq!(invite.result, Some(StatusCode::BusyHere));
+
+        // Timer F still fires
+        let mut request = Transaction::new(TransactionType::Typical, tcp, None);
+        request.start("sip.server.com", 5060);
+        assert!(request.poll(Local::now() + Timer::E.duration()).is_none());
+        request.poll(Local::now() + Timer::F.duration());
+        assert_eq!(request.result, Some(StatusCode::RequestTimeout));
+    }
+}
@@ -1,4 +1,10 @@
 /// Handles communicatin through udp
 /// * TODO: this might need restructing when retransmissions are implemented
 pub mod sockets;
+/// SIP over TCP, reused connections and Content-Length framing
+pub mod tcp;
 pub mod timers;
+/// Certificates and verification of the TLS transport
+pub mod tls;
+/// SIP over WebSocket towards a gateway, RFC 7118
+pub mod ws;
@@ -1,6 +1,17 @@
-use crate::{slog::udp_logger, state::options::Verbosity};
+use crate::{
+    commands::helper::get_host,
+    config::{JSONConfiguration, Transport},
+    network::{get_socket_address, get_unspecified},
+    slog::udp_logger,
+    state::options::Verbosity,
+    transmissions::{tcp::TcpTransport, tls::get_connector, ws::WsTransport},
+};
 use rsip::SipMessage;
-use std::{convert::TryFrom, net::UdpSocket};
+use std::{
+    convert::TryFrom,
+    net::{IpAddr, UdpSocket},
+    time::Duration,
+};
 use yansi::Paint;
 
 #[derive(Debug)]
@@ -17,6 +28,89 @@ pub struct SocketV4 {
     pub bytes: Vec<u8>,
 }
 
+/// Signalling socket of the transport the account uses
+pub enum SipSocket {
+    Udp(UdpSocket),
+    Tcp(TcpTransport),
+    Tls(TcpTransport),
+    Ws(WsTransport),
+}
+
+impl SipSocket {
+    /// Listens on `port` of the configured local address, or of any address of the family of `ip`, with UDP the socket is connected to the SIP server,
+    /// with TLS and WebSocket there is no listener and the SIP server gets connected on the first message
+    pub fn bind(conf: &JSONConfiguration, ip: &IpAddr, port: u16) -> Self {
+        match conf.transport {
+            Transport::Udp => {
+                let socket = UdpSocket::bind(get_bind_address(conf, ip, port)).unwrap();
+                // kept well below T1, so retransmissions are not held back by an idle socket
+                let _io_result = socket.set_read_timeout(Some(Duration::from_millis(100)));
+                socket
+                    .connect(get_socket_address(&conf.sip_server, conf.sip_port))
+                    .expect("connect function failed");
+                SipSocket::Udp(socket)
+            }
+            Transport::Tcp => {
+                SipSocket::Tcp(TcpTransport::bind(&get_bind_address(conf, ip, port)).unwrap())
+            }
+            Transport::Tls => SipSocket::Tls(TcpTransport::tls(
+                get_connector(&conf.tls).expect("invalid TLS configuration"),
+            )),
+            Transport::Ws | Transport::Wss => {
+                let secure = conf.transport == Transport::Wss;
+                let url = format!(
+                    "{}://{}:{}{}",
+                    if secure { "wss" } else { "ws" },
+                    get_host(&conf.sip_server),
+                    &conf.sip_port,
+                    conf.ws_path.as_deref().unwrap_or("/")
+                );
+                let connector = match secure {
+                    true => Some(get_connector(&conf.tls).expect("invalid TLS configuration")),
+                    false => None,
+                };
+                SipSocket::Ws(WsTransport::new(url, connector))
+            }
+        }
+    }
+
+    pub fn send(&mut self, data: &SocketV4, vrb: &Verbosity) {
+        match self {
+            SipSocket::Udp(socket) => send(socket, data, vrb),
+            SipSocket::Tcp(transport) | SipSocket::Tls(transport) => transport.send(data, vrb),
+            SipSocket::Ws(transport) => transport.send(data, vrb),
+        }
+    }
+
+    /// Picks up the next message, if one arrived
+    pub fn receive(&mut self, buffer: &mut [u8; 65535], vrb: &Verbosity) -> Option<SipMessage> {
+        match self {
+            SipSocket::Udp(socket) => match peek(socket, buffer) {
+                0 => None,
+                _ => receive(socket, buffer, vrb).ok(),
+            },
+            SipSocket::Tcp(transport) | SipSocket::Tls(transport) => match transport.receive(vrb) {
+                Some(message) => message.ok(),
+                None => {
+                    // nothing arrived on any connection, as the UDP read timeout would
+                    std::thread::sleep(Duration::from_millis(10));
+                    None
+                }
+            },
+            // reads wait as long as the UDP read timeout
+            SipSocket::Ws(transport) => transport.receive(vrb).and_then(|message| message.ok()),
+        }
+    }
+}
+
+/// Address the SIP socket listens on, the configured local address if there is one
+fn get_bind_address(conf: &JSONConfiguration, ip: &IpAddr, port: u16) -> String {
+    match conf.local_ip {
+        Some(local_ip) => get_socket_address(&local_ip.to_string(), port),
+        None => format!("{}:{}", get_unspecified(ip), port),
+    }
+}
+
 /// Sends a udp message
 pub fn send(socket: &mut UdpSocket, data: &SocketV4, vrb: &Verbosity) {
     udp_logger(
@@ -25,7 +119,7 @@ pub fn send(socket: &mut UdpSocket, data: &SocketV4, vrb: &Verbosity) {
     );
 
     socket
-        .send_to(&data.bytes, format!("{}:{}", &data.ip, &data.port))
+        .send_to(&data.bytes, get_socket_address(&data.ip, data.port))
         .unwrap();
 }
 
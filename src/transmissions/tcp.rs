@@ -0,0 +1,249 @@
+use crate::{
+    network::get_socket_address, slog::udp_logger, state::options::Verbosity,
+    transmissions::sockets::SocketV4,
+};
+use native_tls::{TlsConnector, TlsStream};
+use rsip::SipMessage;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+use yansi::Paint;
+
+/// Stream of a connection, plain or wrapped in TLS
+enum Stream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.read(buf),
+            Stream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buf),
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// A connection, along with whatever arrived on it that is not a whole message yet
+struct Connection {
+    stream: Stream,
+    buffer: Vec<u8>,
+}
+
+impl Connection {
+    fn new(stream: Stream) -> Self {
+        Self {
+            stream,
+            buffer: vec![],
+        }
+    }
+}
+
+/// Reads give up right away, so a quiet connection does not hold up the others
+fn set_quick_reads(stream: &TcpStream) {
+    let _io_result = stream.set_read_timeout(Some(Duration::from_millis(1)));
+}
+
+/// SIP over TCP, or TLS, one connection per remote address, reused for every
+/// message in either direction, the ones the other side opened included
+pub struct TcpTransport {
+    listener: Option<TcpListener>,
+    connector: Option<TlsConnector>,
+    connections: HashMap<String, Connection>,
+}
+
+impl TcpTransport {
+    pub fn bind(address: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener: Some(listener),
+            connector: None,
+            connections: HashMap::new(),
+        })
+    }
+
+    /// TLS towards the SIP server, without a listener of our own
+    /// the server sends its requests over the connection we opened
+    pub fn tls(connector: TlsConnector) -> Self {
+        Self {
+            listener: None,
+            connector: Some(connector),
+            connections: HashMap::new(),
+        }
+    }
+
+    fn open(&self, address: &str, host: &str) -> io::Result<Stream> {
+        let stream = TcpStream::connect(address)?;
+
+        match &self.connector {
+            Some(connector) => {
+                let stream = connector
+                    .connect(host, stream)
+                    .map_err(|err| io::Error::new(ErrorKind::Other, err.to_string()))?;
+                // only once the handshake is done, it needs blocking reads
+                set_quick_reads(stream.get_ref());
+                Ok(Stream::Tls(Box::new(stream)))
+            }
+            None => {
+                set_quick_reads(&stream);
+                Ok(Stream::Plain(stream))
+            }
+        }
+    }
+
+    /// Sends over the connection to the address, opening one if there is none
+    pub fn send(&mut self, data: &SocketV4, vrb: &Verbosity) {
+        udp_logger(
+            Paint::yellow(String::from_utf8_lossy(&data.bytes).to_string()).to_string(),
+            vrb,
+        );
+
+        let address = get_socket_address(&data.ip, data.port);
+        let (host, _port) = address.rsplit_once(':').unwrap();
+        // a connection the other side opened comes from a port of its own,
+        // without a listener everything goes over the connections we opened
+        let key = match self.connections.contains_key(&address) {
+            true => Some(address.clone()),
+            false => self
+                .connections
+                .keys()
+                .find(|key| key.rsplit_once(':').map(|(ip, _)| ip) == Some(host))
+                .or_else(|| match self.listener {
+                    Some(_) => None,
+                    None => self.connections.keys().next(),
+                })
+                .cloned(),
+        };
+
+        if let Some(key) = key {
+            let connection = self.connections.get_mut(&key).unwrap();
+            match connection.stream.write_all(&data.bytes) {
+                Ok(()) => return,
+                Err(err) => {
+                    warn!("connection to {} lost, {:?}", key, err);
+                    self.connections.remove(&key);
+                }
+            }
+        }
+
+        let connected = self
+            .open(&address, host.trim_matches(|c| c == '[' || c == ']'))
+            .and_then(|mut stream| {
+                stream.write_all(&data.bytes)?;
+                Ok(stream)
+            });
+        match connected {
+            Ok(stream) => {
+                self.connections.insert(address, Connection::new(stream));
+            }
+            Err(err) => error!("could not connect to {}, {:?}", address, err),
+        }
+    }
+
+    /// Picks up a whole message from any connection, if one arrived,
+    /// connections the other side opened meanwhile get taken on first
+    pub fn receive(&mut self, vrb: &Verbosity) -> Option<Result<SipMessage, rsip::Error>> {
+        if let Some(listener) = &self.listener {
+            while let Ok((stream, peer)) = listener.accept() {
+                let _io_result = stream.set_nonblocking(false);
+                set_quick_reads(&stream);
+                self.connections
+                    .insert(peer.to_string(), Connection::new(Stream::Plain(stream)));
+            }
+        }
+
+        let mut chunk = [0_u8; 65535];
+        let mut closed: Vec<String> = vec![];
+        let mut received: Option<Vec<u8>> = None;
+        for (address, connection) in self.connections.iter_mut() {
+            match connection.stream.read(&mut chunk) {
+                Ok(0) => closed.push(address.clone()),
+                Ok(amount) => connection.buffer.extend_from_slice(&chunk[..amount]),
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(_) => closed.push(address.clone()),
+            }
+            if received.is_none() {
+                received = frame(&mut connection.buffer);
+            }
+        }
+        for address in closed {
+            info!("connection to {} closed", address);
+            self.connections.remove(&address);
+        }
+
+        let message = String::from_utf8_lossy(&received?).to_string();
+        udp_logger(Paint::green(message.clone()).to_string(), vrb);
+
+        Some(SipMessage::try_from(message))
+    }
+}
+
+/// Cuts the first whole message off the bytes a connection received, the body ends
+/// where Content-Length says, CRLF keep-alives in between messages are dropped
+pub fn frame(buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let start = buffer
+        .iter()
+        .position(|byte| *byte != b'\r' && *byte != b'\n')
+        .unwrap_or(buffer.len());
+    buffer.drain(..start);
+
+    let head = buffer.windows(4).position(|window| window == b"\r\n\r\n")? + 4;
+    let length = String::from_utf8_lossy(&buffer[..head])
+        .lines()
+        .find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            let key = key.trim();
+            match key.eq_ignore_ascii_case("content-length") || key.eq_ignore_ascii_case("l") {
+                true => value.trim().parse::<usize>().ok(),
+                false => None,
+            }
+        })
+        .unwrap_or(0);
+
+    if buffer.len() < head + length {
+        return None;
+    }
+
+    Some(buffer.drain(..head + length).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transmissions::tcp::frame;
+
+    #[test]
+    fn stream_framing() {
+        let options = "OPTIONS sip:1001@10.0.0.1 SIP/2.0\r\nContent-Length: 0\r\n\r\n";
+        let message = "MESSAGE sip:1001@10.0.0.1 SIP/2.0\r\nl: 5\r\n\r\nhello";
+
+        let mut buffer = format!("\r\n\r\n{}{}", options, &message[..40]).into_bytes();
+        assert_eq!(frame(&mut buffer), Some(options.as_bytes().to_vec()));
+        assert_eq!(frame(&mut buffer), None);
+
+        buffer.extend_from_slice(message[40..].as_bytes());
+        assert_eq!(frame(&mut buffer), Some(message.as_bytes().to_vec()));
+        assert!(buffer.is_empty());
+    }
+}
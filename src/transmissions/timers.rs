@@ -1,10 +1,34 @@
-#[allow(dead_code)]
-#[allow(dead_code)]
+/// RFC 3261 timer values in milliseconds
 pub struct Timer(i32);
 
-#[allow(dead_code)]
 impl Timer {
-    const T1: Self = Self(500);
-    const T2: Self = Self(4000);
-    const T4: Self = Self(5000);
+    /// RTT estimate
+    pub const T1: Self = Self(500);
+    /// Maximum retransmit interval for non-INVITE requests and INVITE responses
+    pub const T2: Self = Self(4000);
+    /// Maximum duration a message will remain in the network
+    pub const T4: Self = Self(5000);
+
+    /// INVITE request retransmit interval, doubles on every retransmission
+    pub const A: Self = Self::T1;
+    /// INVITE transaction timeout
+    pub const B: Self = Self(64 * Self::T1.0);
+    /// Wait time for response retransmits after a final INVITE failure
+    pub const D: Self = Self(32000);
+    /// non-INVITE request retransmit interval, capped at T2
+    pub const E: Self = Self::T1;
+    /// non-INVITE transaction timeout
+    pub const F: Self = Self(64 * Self::T1.0);
+    /// INVITE final response retransmit interval, capped at T2
+    pub const G: Self = Self::T1;
+    /// Wait time for the ACK of an INVITE final response
+    pub const H: Self = Self(64 * Self::T1.0);
+    /// Wait time for ACK retransmits
+    pub const I: Self = Self::T4;
+    /// Wait time for response retransmits of a non-INVITE transaction
+    pub const K: Self = Self::T4;
+
+    pub fn duration(&self) -> chrono::Duration {
+        chrono::Duration::milliseconds(self.0 as i64)
+    }
 }
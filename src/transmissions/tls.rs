@@ -0,0 +1,165 @@
+use crate::config::{TlsConfiguration, TlsVerify};
+use native_tls::{Certificate, Identity, TlsConnector};
+use std::{fs, io};
+
+const PEM_END: &str = "-----END CERTIFICATE-----";
+
+/// Connector of the `tls` transport, trusting the configured CA bundle besides
+/// the system store and presenting the client certificate, if there is one
+pub fn get_connector(conf: &TlsConfiguration) -> io::Result<TlsConnector> {
+    let mut builder = TlsConnector::builder();
+
+    if let Some(ca) = &conf.ca {
+        for certificate in split_pem(&fs::read_to_string(ca)?) {
+            builder.add_root_certificate(
+                Certificate::from_pem(certificate.as_bytes()).map_err(to_io_error)?,
+            );
+        }
+    }
+    if let (Some(cert), Some(key)) = (&conf.cert, &conf.key) {
+        builder.identity(
+            Identity::from_pkcs8(&fs::read(cert)?, &fs::read(key)?).map_err(to_io_error)?,
+        );
+    }
+
+    match conf.verify {
+        TlsVerify::Full => {}
+        TlsVerify::Ca => {
+            builder.danger_accept_invalid_hostnames(true);
+        }
+        TlsVerify::None => {
+            warn!("the certificate of the SIP server is not verified");
+            builder.danger_accept_invalid_certs(true);
+            builder.danger_accept_invalid_hostnames(true);
+        }
+    }
+
+    builder.build().map_err(to_io_error)
+}
+
+/// Certificates of a PEM bundle, one by one
+pub fn split_pem(bundle: &str) -> Vec<String> {
+    bundle
+        .split_inclusive(PEM_END)
+        .filter(|certificate| certificate.contains(PEM_END))
+        .map(|certificate| certificate.trim().to_string())
+        .collect()
+}
+
+fn to_io_error(err: native_tls::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::{TlsConfiguration, TlsVerify},
+        state::options::Verbosity,
+        transmissions::{
+            sockets::SocketV4,
+            tcp::{frame, TcpTransport},
+            tls::{get_connector, split_pem},
+        },
+    };
+    use native_tls::{Identity, TlsAcceptor};
+    use rsip::{Method, SipMessage};
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    };
+
+    /// TLS server on a loopback port with a self-signed certificate for `localhost`,
+    /// it sends every message back over the connection it came in on
+    fn echo_server(connections: usize) -> (u16, JoinHandle<()>) {
+        let identity = Identity::from_pkcs8(
+            include_bytes!("fixtures/localhost.crt"),
+            include_bytes!("fixtures/localhost.key"),
+        )
+        .unwrap();
+        let acceptor = TlsAcceptor::new(identity).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = thread::spawn(move || {
+            for stream in listener.incoming().take(connections) {
+                // a client that turns the certificate down ends the handshake
+                let mut stream = match acceptor.accept(stream.unwrap()) {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let mut buffer = vec![];
+                let mut chunk = [0_u8; 1024];
+                let message = loop {
+                    if let Some(message) = frame(&mut buffer) {
+                        break message;
+                    }
+                    let amount = stream.read(&mut chunk).unwrap();
+                    buffer.extend_from_slice(&chunk[..amount]);
+                };
+                stream.write_all(&message).unwrap();
+            }
+        });
+
+        (port, server)
+    }
+
+    #[test]
+    fn self_signed_loopback() {
+        let (port, server) = echo_server(2);
+
+        let full = get_connector(&TlsConfiguration::default()).unwrap();
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        assert!(full.connect("localhost", stream).is_err());
+
+        let none = get_connector(&TlsConfiguration {
+            verify: TlsVerify::None,
+            ..Default::default()
+        })
+        .unwrap();
+        let mut transport = TcpTransport::tls(none);
+        let options = "OPTIONS sip:1001@127.0.0.1 SIP/2.0\r\nContent-Length: 0\r\n\r\n";
+        transport.send(
+            &SocketV4 {
+                ip: "127.0.0.1".to_string(),
+                port,
+                bytes: options.as_bytes().to_vec(),
+            },
+            &Verbosity::Quiet,
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let received = loop {
+            if let Some(received) = transport.receive(&Verbosity::Quiet) {
+                break received.unwrap();
+            }
+            assert!(Instant::now() < deadline, "nothing came back over TLS");
+        };
+        assert!(matches!(
+            received,
+            SipMessage::Request(request) if request.method == Method::Options
+        ));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn pem_bundle() {
+        let certificate = |body: &str| {
+            format!(
+                "-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----",
+                body
+            )
+        };
+        let bundle = format!(
+            "# root\n{}\n{}\n\n",
+            certificate("MIIB"),
+            certificate("MIIC")
+        );
+
+        let certificates = split_pem(&bundle);
+        assert_eq!(certificates.len(), 2);
+        assert_eq!(certificates[0], format!("# root\n{}", certificate("MIIB")));
+        assert_eq!(certificates[1], certificate("MIIC"));
+    }
+}
@@ -0,0 +1,249 @@
+use crate::{slog::udp_logger, state::options::Verbosity, transmissions::sockets::SocketV4};
+use native_tls::TlsConnector;
+use rsip::SipMessage;
+use std::{
+    convert::TryFrom,
+    io::{self, ErrorKind},
+    net::TcpStream,
+    time::Duration,
+};
+use tungstenite::{
+    client::IntoClientRequest, client_tls_with_config, handshake::client::Response,
+    http::HeaderValue, stream::MaybeTlsStream, Connector, Message, WebSocket,
+};
+use yansi::Paint;
+
+/// SIP over WebSocket (RFC 7118), a single connection to the gateway with the `sip`
+/// subprotocol, every message in either direction is a frame of its own
+pub struct WsTransport {
+    url: String,
+    connector: Option<TlsConnector>,
+    socket: Option<WebSocket<MaybeTlsStream<TcpStream>>>,
+}
+
+impl WsTransport {
+    /// Nothing gets connected before the first message, `wss:` urls use the connector
+    pub fn new(url: String, connector: Option<TlsConnector>) -> Self {
+        Self {
+            url,
+            connector,
+            socket: None,
+        }
+    }
+
+    fn connect(&self) -> io::Result<WebSocket<MaybeTlsStream<TcpStream>>> {
+        let mut request = self
+            .url
+            .as_str()
+            .into_client_request()
+            .map_err(to_io_error)?;
+        request
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", HeaderValue::from_static("sip"));
+
+        let secure = request.uri().scheme_str() == Some("wss");
+        // an IPv6 host comes within brackets in the url, but not for the connect
+        let host = request
+            .uri()
+            .host()
+            .unwrap_or_default()
+            .trim_matches(|c| c == '[' || c == ']')
+            .to_string();
+        let port = request
+            .uri()
+            .port_u16()
+            .unwrap_or(if secure { 443 } else { 80 });
+        let stream = TcpStream::connect((host.as_str(), port))?;
+
+        let (socket, response) = client_tls_with_config(
+            request,
+            stream,
+            None,
+            self.connector.clone().map(Connector::NativeTls),
+        )
+        .map_err(|err| io::Error::new(ErrorKind::Other, err.to_string()))?;
+        if !has_sip_protocol(&response) {
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                "the gateway did not agree to the sip subprotocol",
+            ));
+        }
+
+        // only once the handshake is done, it needs blocking reads
+        let stream = match socket.get_ref() {
+            MaybeTlsStream::Plain(stream) => Some(stream),
+            MaybeTlsStream::NativeTls(stream) => Some(stream.get_ref()),
+            _ => None,
+        };
+        if let Some(stream) = stream {
+            // kept well below T1, as the UDP read timeout
+            let _io_result = stream.set_read_timeout(Some(Duration::from_millis(100)));
+        }
+
+        Ok(socket)
+    }
+
+    /// Sends to the gateway whatever the address, reconnecting when the connection got lost
+    pub fn send(&mut self, data: &SocketV4, vrb: &Verbosity) {
+        let message = String::from_utf8_lossy(&data.bytes).to_string();
+        udp_logger(Paint::yellow(message.clone()).to_string(), vrb);
+
+        if let Some(socket) = &mut self.socket {
+            match socket.send(Message::Text(message.clone())) {
+                Ok(()) => return,
+                Err(err) => {
+                    warn!("connection to {} lost, {:?}", self.url, err);
+                    self.socket = None;
+                }
+            }
+        }
+
+        let connected = self.connect().and_then(|mut socket| {
+            socket.send(Message::Text(message)).map_err(to_io_error)?;
+            Ok(socket)
+        });
+        match connected {
+            Ok(socket) => self.socket = Some(socket),
+            Err(err) => error!("could not connect to {}, {:?}", self.url, err),
+        }
+    }
+
+    /// Picks up the next message frame, if one arrived, pings and pongs are left to tungstenite
+    pub fn receive(&mut self, vrb: &Verbosity) -> Option<Result<SipMessage, rsip::Error>> {
+        let socket = match self.socket.as_mut() {
+            Some(socket) => socket,
+            None => {
+                // not connected yet, as the UDP read timeout would
+                std::thread::sleep(Duration::from_millis(100));
+                return None;
+            }
+        };
+
+        let message = match socket.read() {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Binary(bytes)) => String::from_utf8_lossy(&bytes).to_string(),
+            Ok(Message::Close(_)) => {
+                info!("connection to {} closed", self.url);
+                self.socket = None;
+                return None;
+            }
+            Ok(_) => return None,
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                return None
+            }
+            Err(err) => {
+                warn!("connection to {} lost, {:?}", self.url, err);
+                self.socket = None;
+                return None;
+            }
+        };
+        udp_logger(Paint::green(message.clone()).to_string(), vrb);
+
+        Some(SipMessage::try_from(message))
+    }
+}
+
+/// Whether the handshake response picked the `sip` subprotocol (RFC 7118 4.1),
+/// without it the other side does not speak SIP over the connection
+fn has_sip_protocol(response: &Response) -> bool {
+    response
+        .headers()
+        .get("Sec-WebSocket-Protocol")
+        .and_then(|protocol| protocol.to_str().ok())
+        .map_or(false, |protocol| {
+            protocol.trim().eq_ignore_ascii_case("sip")
+        })
+}
+
+fn to_io_error(err: tungstenite::Error) -> io::Error {
+    io::Error::new(ErrorKind::Other, err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        state::options::Verbosity,
+        transmissions::{sockets::SocketV4, ws::WsTransport},
+    };
+    use rsip::{Method, SipMessage};
+    use std::{
+        net::TcpListener,
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    };
+    use tungstenite::{
+        accept_hdr,
+        handshake::server::{Request, Response},
+        http::HeaderValue,
+    };
+
+    /// WebSocket server on a loopback port sending back the first frame it gets,
+    /// it only agrees to the `sip` subprotocol when `sip` is set
+    fn echo_server(sip: bool) -> (u16, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = accept_hdr(stream, |request: &Request, mut response: Response| {
+                let asked = request.headers().get("Sec-WebSocket-Protocol").cloned();
+                if sip && asked == Some(HeaderValue::from_static("sip")) {
+                    response
+                        .headers_mut()
+                        .insert("Sec-WebSocket-Protocol", HeaderValue::from_static("sip"));
+                }
+                Ok(response)
+            })
+            .unwrap();
+            if let Ok(message) = socket.read() {
+                let _ws_result = socket.send(message);
+            }
+        });
+
+        (port, server)
+    }
+
+    fn options(port: u16) -> SocketV4 {
+        SocketV4 {
+            ip: "127.0.0.1".to_string(),
+            port,
+            bytes: b"OPTIONS sip:1001@127.0.0.1 SIP/2.0\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        }
+    }
+
+    #[test]
+    fn sip_subprotocol_loopback() {
+        let (port, server) = echo_server(true);
+        let mut transport = WsTransport::new(format!("ws://127.0.0.1:{}/", port), None);
+        transport.send(&options(port), &Verbosity::Quiet);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let received = loop {
+            if let Some(received) = transport.receive(&Verbosity::Quiet) {
+                break received.unwrap();
+            }
+            assert!(
+                Instant::now() < deadline,
+                "nothing came back over the WebSocket"
+            );
+        };
+        assert!(matches!(
+            received,
+            SipMessage::Request(request) if request.method == Method::Options
+        ));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn gateway_without_sip_subprotocol() {
+        let (port, server) = echo_server(false);
+        let mut transport = WsTransport::new(format!("ws://127.0.0.1:{}/", port), None);
+        transport.send(&options(port), &Verbosity::Quiet);
+
+        assert!(transport.socket.is_none());
+        drop(transport);
+        server.join().unwrap();
+    }
+}